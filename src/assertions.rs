@@ -0,0 +1,339 @@
+use regex::Regex;
+use serde::{Deserialize, Deserializer, Serialize};
+use serde_json::Value;
+
+use crate::failure::FailureReason;
+use crate::InputUrl;
+
+/// Assertions evaluated against the response body of a probe.
+#[derive(Default)]
+pub struct BodyAssertions {
+    must_contain: Vec<String>,
+    must_not_contain: Vec<String>,
+    must_match: Vec<Regex>,
+    must_not_match: Vec<Regex>,
+    json: Vec<(JsonAssertion, Vec<Segment>)>,
+}
+
+/// An assertion on a single field of a JSON response body.
+#[derive(Deserialize, Serialize, Clone)]
+#[serde(deny_unknown_fields)]
+pub struct JsonAssertion {
+    /// JSONPath-style location of the field, e.g. `$.checks[0].status`.
+    path: String,
+    #[serde(
+        default,
+        deserialize_with = "present",
+        skip_serializing_if = "Option::is_none"
+    )]
+    equals: Option<Value>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    exists: Option<bool>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    gt: Option<f64>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    gte: Option<f64>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    lt: Option<f64>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    lte: Option<f64>,
+}
+
+/// A failed assertion, with the JSON field it was about if any.
+pub struct AssertionFailure {
+    pub reason: FailureReason,
+    pub field: Option<String>,
+    pub message: String,
+}
+
+impl AssertionFailure {
+    fn body(message: String) -> Self {
+        Self {
+            reason: FailureReason::BodyAssertion,
+            field: None,
+            message: format!("Body assertion failed: {message}"),
+        }
+    }
+
+    fn json(field: Option<&str>, message: String) -> Self {
+        Self {
+            reason: FailureReason::JsonAssertion,
+            field: field.map(str::to_string),
+            message: format!("JSON assertion failed: {message}"),
+        }
+    }
+}
+
+#[derive(Debug, PartialEq)]
+enum Segment {
+    Key(String),
+    Index(usize),
+}
+
+impl BodyAssertions {
+    /// Compile the body assertions of `input`, failing on the first invalid pattern or path.
+    pub fn new(input: &InputUrl) -> Result<Self, String> {
+        let compile = |patterns: &Option<Vec<String>>| {
+            patterns
+                .iter()
+                .flatten()
+                .map(|pattern| {
+                    Regex::new(pattern).map_err(|e| format!("Invalid pattern `{pattern}`: {e}"))
+                })
+                .collect::<Result<Vec<_>, _>>()
+        };
+
+        let json = input
+            .json_assertions
+            .iter()
+            .flatten()
+            .map(|assertion| Ok((assertion.clone(), parse_path(&assertion.path)?)))
+            .collect::<Result<Vec<_>, String>>()?;
+
+        Ok(Self {
+            must_contain: input.must_contain.clone().unwrap_or_default(),
+            must_not_contain: input.must_not_contain.clone().unwrap_or_default(),
+            must_match: compile(&input.must_match)?,
+            must_not_match: compile(&input.must_not_match)?,
+            json,
+        })
+    }
+
+    /// Check `body` against every assertion, describing the first one that fails.
+    pub fn check(&self, body: &str) -> Result<(), AssertionFailure> {
+        if let Some(keyword) = self
+            .must_contain
+            .iter()
+            .find(|k| !body.contains(k.as_str()))
+        {
+            return Err(AssertionFailure::body(format!(
+                "body does not contain `{keyword}`."
+            )));
+        }
+
+        if let Some(keyword) = self
+            .must_not_contain
+            .iter()
+            .find(|k| body.contains(k.as_str()))
+        {
+            return Err(AssertionFailure::body(format!(
+                "body contains `{keyword}`."
+            )));
+        }
+
+        if let Some(regex) = self.must_match.iter().find(|r| !r.is_match(body)) {
+            return Err(AssertionFailure::body(format!(
+                "body does not match `{regex}`."
+            )));
+        }
+
+        if let Some(regex) = self.must_not_match.iter().find(|r| r.is_match(body)) {
+            return Err(AssertionFailure::body(format!("body matches `{regex}`.")));
+        }
+
+        if self.json.is_empty() {
+            return Ok(());
+        }
+
+        let document = serde_json::from_str::<Value>(body)
+            .map_err(|e| AssertionFailure::json(None, format!("body is not valid JSON: {e}.")))?;
+
+        for (assertion, segments) in &self.json {
+            let value = segments
+                .iter()
+                .try_fold(&document, |value, segment| match segment {
+                    Segment::Key(key) => value.get(key.as_str()),
+                    Segment::Index(index) => value.get(*index),
+                });
+
+            assertion
+                .check(value)
+                .map_err(|message| AssertionFailure::json(Some(&assertion.path), message))?;
+        }
+
+        Ok(())
+    }
+}
+
+impl JsonAssertion {
+    fn check(&self, value: Option<&Value>) -> Result<(), String> {
+        let path = &self.path;
+
+        let value = match (value, self.exists) {
+            (Some(_), Some(false)) => return Err(format!("`{path}` exists.")),
+            (None, Some(false)) => return Ok(()),
+            (None, _) => return Err(format!("`{path}` does not exist.")),
+            (Some(value), _) => value,
+        };
+
+        if let Some(expected) = &self.equals {
+            if value != expected {
+                return Err(format!("`{path}` is `{value}`, expected `{expected}`."));
+            }
+        }
+
+        let thresholds = [
+            ("greater than", self.gt, f64::gt as fn(&f64, &f64) -> bool),
+            ("at least", self.gte, f64::ge),
+            ("less than", self.lt, f64::lt),
+            ("at most", self.lte, f64::le),
+        ];
+
+        for (name, threshold, compare) in thresholds {
+            let Some(threshold) = threshold else {
+                continue;
+            };
+
+            let Some(number) = value.as_f64() else {
+                return Err(format!("`{path}` is `{value}`, expected a number."));
+            };
+
+            if !compare(&number, &threshold) {
+                return Err(format!(
+                    "`{path}` is `{number}`, expected {name} `{threshold}`."
+                ));
+            }
+        }
+
+        Ok(())
+    }
+}
+
+/// Parse a JSONPath-style location made of `.key`, `['key']` and `[index]` segments. The leading
+/// `$` is optional, quoted keys may contain any character but their quote.
+fn parse_path(path: &str) -> Result<Vec<Segment>, String> {
+    let invalid = || format!("Invalid JSON path `{path}`.");
+
+    let normalized = match path.strip_prefix('$') {
+        Some(rest) => rest.to_string(),
+        None if path.starts_with('[') => path.to_string(),
+        None => format!(".{path}"),
+    };
+
+    let mut segments = Vec::new();
+    let mut rest = normalized.as_str();
+
+    while !rest.is_empty() {
+        if let Some(after) = rest.strip_prefix('.') {
+            let end = after.find(['.', '[']).unwrap_or(after.len());
+            if end == 0 {
+                return Err(invalid());
+            }
+
+            segments.push(Segment::Key(after[..end].to_string()));
+            rest = &after[end..];
+        } else if let Some(after) = rest.strip_prefix('[') {
+            let quote = after.chars().next().filter(|c| ['\'', '"'].contains(c));
+
+            rest = match quote {
+                Some(quote) => {
+                    let key = &after[1..];
+                    let end = key.find(quote).ok_or_else(invalid)?;
+                    segments.push(Segment::Key(key[..end].to_string()));
+                    key[end + 1..].strip_prefix(']').ok_or_else(invalid)?
+                }
+                None => {
+                    let end = after.find(']').ok_or_else(invalid)?;
+                    let index = after[..end].trim().parse().map_err(|_| invalid())?;
+                    segments.push(Segment::Index(index));
+                    &after[end + 1..]
+                }
+            };
+        } else {
+            return Err(invalid());
+        }
+    }
+
+    Ok(segments)
+}
+
+/// Keep an explicit `null` as `Some(Value::Null)` rather than treating it as missing.
+fn present<'de, D: Deserializer<'de>>(deserializer: D) -> Result<Option<Value>, D::Error> {
+    Value::deserialize(deserializer).map(Some)
+}
+
+#[cfg(test)]
+mod tests {
+    use serde_json::json;
+
+    use super::*;
+
+    fn key(key: &str) -> Segment {
+        Segment::Key(key.to_string())
+    }
+
+    fn assert_json(path: &str, assertion: Value, body: &str) -> Result<(), String> {
+        let mut assertion = assertion;
+        assertion["path"] = path.into();
+        let assertion: JsonAssertion = serde_json::from_value(assertion).unwrap();
+
+        let assertions = BodyAssertions {
+            json: vec![(assertion, parse_path(path)?)],
+            ..Default::default()
+        };
+        assertions.check(body).map_err(|failure| failure.message)
+    }
+
+    #[test]
+    fn parses_keys_and_indexes() {
+        assert_eq!(parse_path("$.status"), Ok(vec![key("status")]));
+        assert_eq!(parse_path("status"), Ok(vec![key("status")]));
+        assert_eq!(parse_path("$"), Ok(vec![]));
+        assert_eq!(
+            parse_path("$.checks[0].status"),
+            Ok(vec![key("checks"), Segment::Index(0), key("status")])
+        );
+        assert_eq!(
+            parse_path("[2][ 10 ]"),
+            Ok(vec![Segment::Index(2), Segment::Index(10)])
+        );
+    }
+
+    #[test]
+    fn parses_quoted_keys() {
+        assert_eq!(
+            parse_path("$['db:latency'].value"),
+            Ok(vec![key("db:latency"), key("value")])
+        );
+        assert_eq!(parse_path(r#"$["a.b"]"#), Ok(vec![key("a.b")]));
+        assert_eq!(parse_path("$['a]b[0']"), Ok(vec![key("a]b[0")]));
+        assert_eq!(parse_path(r#"$['say "hi"']"#), Ok(vec![key(r#"say "hi""#)]));
+        assert_eq!(parse_path("$['']"), Ok(vec![key("")]));
+    }
+
+    #[test]
+    fn rejects_invalid_paths() {
+        for path in [
+            "$.", "$..a", "$a", "$.a.", "$[", "$[0", "$[-1]", "$[a]", "$[1.5]", "$['a]", "$['a'b]",
+            "$['a']b", "a[0]]",
+        ] {
+            assert_eq!(
+                parse_path(path),
+                Err(format!("Invalid JSON path `{path}`.")),
+                "{path}"
+            );
+        }
+    }
+
+    #[test]
+    fn resolves_indexes_within_bounds_only() {
+        let body = r#"{"checks": [{"status": "pass"}]}"#;
+
+        assert_eq!(
+            assert_json("$.checks[0].status", json!({"equals": "pass"}), body),
+            Ok(())
+        );
+        assert_eq!(
+            assert_json("$.checks[1].status", json!({"exists": false}), body),
+            Ok(())
+        );
+        assert_eq!(
+            assert_json("$.checks[1].status", json!({}), body),
+            Err("JSON assertion failed: `$.checks[1].status` does not exist.".to_string())
+        );
+        assert_eq!(
+            assert_json("$.checks.status", json!({}), body),
+            Err("JSON assertion failed: `$.checks.status` does not exist.".to_string())
+        );
+    }
+}
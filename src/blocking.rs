@@ -0,0 +1,95 @@
+use worker::Headers;
+
+/// A bot challenge, captcha or WAF block answered instead of the probed page.
+pub struct Block {
+    pub vendor: &'static str,
+    pub kind: &'static str,
+}
+
+/// A vendor, a header name with its expected value if any, and the kind of block it identifies.
+type HeaderSignature = (
+    &'static str,
+    &'static str,
+    Option<&'static str>,
+    &'static str,
+);
+
+/// Headers only sent by a vendor on blocked or challenged responses.
+const HEADER_SIGNATURES: &[HeaderSignature] = &[
+    (
+        "Cloudflare",
+        "cf-mitigated",
+        Some("challenge"),
+        "managed challenge",
+    ),
+    ("AWS WAF", "x-amzn-waf-action", Some("captcha"), "captcha"),
+    (
+        "AWS WAF",
+        "x-amzn-waf-action",
+        Some("challenge"),
+        "challenge",
+    ),
+];
+
+/// Headers identifying a vendor's block page, only checked on error responses.
+const ERROR_HEADER_SIGNATURES: &[HeaderSignature] = &[
+    ("Akamai", "server", Some("akamaighost"), "WAF block"),
+    ("Imperva", "x-iinfo", None, "WAF block"),
+    ("DataDome", "x-datadome", None, "captcha"),
+    ("Sucuri", "x-sucuri-block", None, "WAF block"),
+];
+
+/// A vendor, a snippet of its block page and the kind of block it identifies, only checked on
+/// error responses.
+const BODY_SIGNATURES: &[(&str, &str, &str)] = &[
+    ("Cloudflare", "challenge-platform", "managed challenge"),
+    ("Cloudflare", "cf_chl_opt", "managed challenge"),
+    ("Cloudflare", "Sorry, you have been blocked", "WAF block"),
+    (
+        "Cloudflare",
+        "Attention Required! | Cloudflare",
+        "WAF block",
+    ),
+    ("Akamai", "errors.edgesuite.net", "WAF block"),
+    ("Imperva", "Incapsula incident ID", "WAF block"),
+    ("Imperva", "_Incapsula_Resource", "bot challenge"),
+    ("AWS WAF", "awswaf.com", "challenge"),
+    ("DataDome", "captcha-delivery.com", "captcha"),
+    ("PerimeterX", "px-captcha", "captcha"),
+    ("Sucuri", "Sucuri WebSite Firewall", "WAF block"),
+    ("hCaptcha", "hcaptcha.com/1/api.js", "captcha"),
+    ("reCAPTCHA", "google.com/recaptcha", "captcha"),
+];
+
+/// Detect a challenge or block page from the status code, headers and body of a response.
+pub fn detect(status_code: u16, headers: &Headers, body: &str) -> Option<Block> {
+    if let Some(block) = find_header(HEADER_SIGNATURES, headers) {
+        return Some(block);
+    }
+
+    // Captcha scripts and vendor headers also show up on regular pages, only trust them when the
+    // page is an error.
+    if status_code < 400 {
+        return None;
+    }
+
+    if let Some(block) = find_header(ERROR_HEADER_SIGNATURES, headers) {
+        return Some(block);
+    }
+
+    BODY_SIGNATURES
+        .iter()
+        .find(|(_, snippet, _)| body.contains(snippet))
+        .map(|&(vendor, _, kind)| Block { vendor, kind })
+}
+
+fn find_header(signatures: &[HeaderSignature], headers: &Headers) -> Option<Block> {
+    signatures
+        .iter()
+        .find(|(_, name, expected, _)| {
+            headers.get(name).ok().flatten().is_some_and(|value| {
+                expected.is_none_or(|expected| value.eq_ignore_ascii_case(expected))
+            })
+        })
+        .map(|&(vendor, _, _, kind)| Block { vendor, kind })
+}
@@ -0,0 +1,126 @@
+use std::sync::LazyLock;
+
+use regex::Regex;
+use serde::{Deserialize, Serialize};
+use worker::Headers;
+
+/// The `1xxx` error code written in a Cloudflare error page.
+static ERROR_CODE: LazyLock<Regex> =
+    LazyLock::new(|| Regex::new(r"(?i)error(?: code)?:?\s*(1\d{3})\b").unwrap());
+
+/// A Cloudflare error returned in front of the probed origin.
+#[derive(Serialize, Deserialize, Clone)]
+pub struct CloudflareError {
+    /// The `52x`/`530` status code, or the `1xxx` error code from the error page when there is one.
+    pub code: u16,
+    pub title: String,
+    pub explanation: String,
+    /// Whether the origin accepted the connection from Cloudflare at all. Cloudflare's edge
+    /// answered the probe either way, so the outage is limited to the origin.
+    pub origin_reachable: bool,
+    pub ray_id: Option<String>,
+}
+
+impl CloudflareError {
+    /// Whether the error means the probed host does not resolve.
+    pub fn is_dns_error(&self) -> bool {
+        matches!(self.code, 1001 | 1016)
+    }
+}
+
+/// Detect a Cloudflare error from the status code, headers and body of a response.
+pub fn detect(status_code: u16, headers: &Headers, body: &str) -> Option<CloudflareError> {
+    if !matches!(status_code, 520..=527 | 530) {
+        return None;
+    }
+
+    let ray_id = headers.get("cf-ray").ok().flatten();
+    let served_by_cloudflare = ray_id.is_some()
+        || headers
+            .get("server")
+            .ok()
+            .flatten()
+            .is_some_and(|server| server.eq_ignore_ascii_case("cloudflare"))
+        || body.contains("cf-error-details")
+        || body.contains("Cloudflare Ray ID");
+
+    if !served_by_cloudflare {
+        return None;
+    }
+
+    // A 530 hides the actual `1xxx` error, which is only written in the error page.
+    let error_code = ERROR_CODE
+        .captures(body)
+        .and_then(|captures| captures.get(1)?.as_str().parse().ok());
+
+    let code = error_code.unwrap_or(status_code);
+    let (title, explanation, origin_reachable) = describe(code);
+
+    Some(CloudflareError {
+        code,
+        title: title.to_string(),
+        explanation: explanation.to_string(),
+        origin_reachable,
+        ray_id,
+    })
+}
+
+fn describe(code: u16) -> (&'static str, &'static str, bool) {
+    match code {
+        520 => (
+            "Web server returned an unknown error",
+            "The origin answered Cloudflare with an empty, unknown or unexpected response.",
+            true,
+        ),
+        521 => (
+            "Web server is down",
+            "The origin refused the connection from Cloudflare.",
+            false,
+        ),
+        522 => (
+            "Connection timed out",
+            "Cloudflare timed out while connecting to the origin.",
+            false,
+        ),
+        523 => (
+            "Origin is unreachable",
+            "Cloudflare could not find a route to the origin.",
+            false,
+        ),
+        524 => (
+            "A timeout occurred",
+            "The origin accepted the connection but did not answer in time.",
+            true,
+        ),
+        525 => (
+            "SSL handshake failed",
+            "The SSL handshake between Cloudflare and the origin failed.",
+            true,
+        ),
+        526 => (
+            "Invalid SSL certificate",
+            "The origin presented an invalid SSL certificate to Cloudflare.",
+            true,
+        ),
+        527 => (
+            "Railgun error",
+            "The connection between Cloudflare and the origin's Railgun listener failed.",
+            false,
+        ),
+        1001 | 1016 => (
+            "Origin DNS error",
+            "Cloudflare could not resolve the origin's hostname.",
+            false,
+        ),
+        1033 => (
+            "Cloudflare Tunnel error",
+            "Cloudflare could not reach the origin through its tunnel.",
+            false,
+        ),
+        _ => (
+            "Cloudflare error",
+            "Cloudflare could not serve the request from the origin.",
+            false,
+        ),
+    }
+}
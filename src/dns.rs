@@ -0,0 +1,382 @@
+use std::fmt;
+use std::future::Future;
+
+use futures::future::try_join;
+use serde::{Deserialize, Serialize};
+use worker::*;
+
+use crate::dnssec::Dnssec;
+use crate::wire::{self, QueryFlags};
+
+/// Resolver used when `DOH_RESOLVERS` is not set.
+pub const DEFAULT_RESOLVER: &str = "https://cloudflare-dns.com/dns-query";
+const DNS_MESSAGE: &str = "application/dns-message";
+
+/// Number of subrequests needed to resolve a host with a single resolver.
+pub const SUBREQUESTS: usize = 2;
+
+/// A DNS-over-HTTPS server (RFC 8484).
+#[derive(Clone, PartialEq)]
+pub struct Resolver {
+    pub url: String,
+    pub method: DohMethod,
+}
+
+/// How queries are sent to a resolver: GET is cacheable, POST gives smaller requests.
+#[derive(Clone, Copy, PartialEq)]
+pub enum DohMethod {
+    Get,
+    Post,
+}
+
+impl DohMethod {
+    pub fn parse(method: &str) -> Option<Self> {
+        match method.trim().to_ascii_uppercase().as_str() {
+            "GET" => Some(DohMethod::Get),
+            "POST" => Some(DohMethod::Post),
+            _ => None,
+        }
+    }
+}
+
+/// A DNS record type that can be queried.
+#[derive(Deserialize, Serialize, Clone, Copy, PartialEq)]
+#[serde(rename_all = "UPPERCASE")]
+pub enum RecordType {
+    #[serde(alias = "a")]
+    A,
+    #[serde(alias = "aaaa")]
+    Aaaa,
+    #[serde(alias = "cname")]
+    Cname,
+    #[serde(alias = "mx")]
+    Mx,
+    #[serde(alias = "txt")]
+    Txt,
+    #[serde(alias = "ns")]
+    Ns,
+    #[serde(alias = "caa")]
+    Caa,
+    #[serde(alias = "srv")]
+    Srv,
+}
+
+impl RecordType {
+    const ALL: [RecordType; 8] = [
+        RecordType::A,
+        RecordType::Aaaa,
+        RecordType::Cname,
+        RecordType::Mx,
+        RecordType::Txt,
+        RecordType::Ns,
+        RecordType::Caa,
+        RecordType::Srv,
+    ];
+
+    pub fn name(self) -> &'static str {
+        match self {
+            RecordType::A => "A",
+            RecordType::Aaaa => "AAAA",
+            RecordType::Cname => "CNAME",
+            RecordType::Mx => "MX",
+            RecordType::Txt => "TXT",
+            RecordType::Ns => "NS",
+            RecordType::Caa => "CAA",
+            RecordType::Srv => "SRV",
+        }
+    }
+
+    /// Numeric type, as registered by IANA.
+    pub fn code(self) -> u16 {
+        match self {
+            RecordType::A => 1,
+            RecordType::Aaaa => 28,
+            RecordType::Cname => 5,
+            RecordType::Mx => 15,
+            RecordType::Txt => 16,
+            RecordType::Ns => 2,
+            RecordType::Caa => 257,
+            RecordType::Srv => 33,
+        }
+    }
+}
+
+/// The answer of a resolver to a single query.
+pub struct Answer {
+    pub rcode: u64,
+    /// The AD flag: the resolver validated the records with DNSSEC. Reported as `authenticated`
+    /// wherever an answer is returned.
+    pub authenticated: bool,
+    pub records: Vec<DnsRecord>,
+}
+
+/// The records a host resolved to, and the resolver that answered.
+#[derive(Serialize, Deserialize, Clone)]
+pub struct DnsReport {
+    pub resolver: String,
+    /// See [`Answer::authenticated`].
+    #[serde(default)]
+    pub authenticated: bool,
+    pub records: Vec<DnsRecord>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub dnssec: Option<Dnssec>,
+    /// Why the DNSSEC status could not be checked.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub dnssec_error: Option<String>,
+}
+
+/// A single answer record, with its data in presentation format.
+#[derive(Serialize, Deserialize, Clone, PartialEq)]
+pub struct DnsRecord {
+    pub name: String,
+    #[serde(rename = "type")]
+    pub record_type: String,
+    pub ttl: u32,
+    pub data: String,
+}
+
+/// Why a host could not be resolved.
+pub enum DnsError {
+    /// The resolver answered with a DNS response code other than `NOERROR`.
+    Rcode(u64),
+    /// The resolver could not be reached or did not answer with a DNS message.
+    Resolver { resolver: String, message: String },
+    /// `SERVFAIL` caused by a DNSSEC validation failure: the host resolves with checking disabled.
+    Dnssec,
+}
+
+impl DnsError {
+    /// HTTP status code a target failing with this error is rejected with.
+    pub fn status(&self) -> u16 {
+        match self {
+            DnsError::Rcode(_) | DnsError::Dnssec => 400,
+            DnsError::Resolver { .. } => 502,
+        }
+    }
+
+    /// Whether the next resolver may answer differently. Only `NXDOMAIN` is a definitive answer
+    /// about the host, other response codes are failures of the resolver itself.
+    fn is_retryable(&self) -> bool {
+        !matches!(self, DnsError::Rcode(3))
+    }
+}
+
+impl fmt::Display for DnsError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            DnsError::Rcode(rcode) => write!(
+                f,
+                "Request does not pass domain check [{}].",
+                rcode_name(*rcode)
+            ),
+            DnsError::Resolver { resolver, message } => {
+                write!(f, "DNS check failed, {resolver} did not answer: {message}")
+            }
+            DnsError::Dnssec => write!(
+                f,
+                "Request does not pass domain check [SERVFAIL]: DNSSEC validation fails, the host \
+                 only resolves with checking disabled."
+            ),
+        }
+    }
+}
+
+/// Resolve the `A` and `AAAA` records of `host`, along with the `CNAME` chain leading to them.
+/// Each resolver is tried in order until one gives a definitive answer.
+pub async fn resolve(
+    host: &str,
+    resolvers: &[Resolver],
+) -> std::result::Result<DnsReport, DnsError> {
+    with_fallback(resolvers, |resolver| resolve_with(host, resolver)).await
+}
+
+/// Look up the `record_type` records of `name`, trying each resolver in order until one gives a
+/// definitive answer. Returns the resolver that answered along with its answer.
+pub async fn lookup(
+    name: &str,
+    record_type: RecordType,
+    resolvers: &[Resolver],
+) -> std::result::Result<(String, Answer), DnsError> {
+    with_fallback(resolvers, |resolver| async move {
+        let answer = query(name, resolver, record_type)
+            .await
+            .map_err(|e| DnsError::Resolver {
+                resolver: resolver.url.clone(),
+                message: e.to_string(),
+            })?;
+
+        match answer.rcode {
+            0 => Ok((resolver.url.clone(), answer)),
+            rcode => Err(DnsError::Rcode(rcode)),
+        }
+    })
+    .await
+}
+
+async fn with_fallback<'a, T, F, Fut>(
+    resolvers: &'a [Resolver],
+    query: F,
+) -> std::result::Result<T, DnsError>
+where
+    F: Fn(&'a Resolver) -> Fut,
+    Fut: Future<Output = std::result::Result<T, DnsError>>,
+{
+    let mut error = None;
+
+    for resolver in resolvers {
+        match query(resolver).await {
+            Ok(answer) => return Ok(answer),
+            Err(e) if e.is_retryable() => {
+                console_log!("query {}: {e}", resolver.url);
+                error = Some(e);
+            }
+            Err(e) => return Err(e),
+        }
+    }
+
+    Err(error.unwrap_or_else(|| DnsError::Resolver {
+        resolver: "DOH_RESOLVERS".to_string(),
+        message: "no resolver is configured.".to_string(),
+    }))
+}
+
+async fn resolve_with(host: &str, resolver: &Resolver) -> std::result::Result<DnsReport, DnsError> {
+    let (a, aaaa) = try_join(
+        query(host, resolver, RecordType::A),
+        query(host, resolver, RecordType::Aaaa),
+    )
+    .await
+    .map_err(|e| DnsError::Resolver {
+        resolver: resolver.url.clone(),
+        message: e.to_string(),
+    })?;
+
+    if a.rcode != 0 {
+        return Err(DnsError::Rcode(a.rcode));
+    }
+
+    let mut records = Vec::new();
+    for record in a.records.into_iter().chain(aaaa.records) {
+        // Both queries return the same `CNAME` chain.
+        if !records.contains(&record) {
+            records.push(record);
+        }
+    }
+
+    Ok(DnsReport {
+        resolver: resolver.url.clone(),
+        authenticated: a.authenticated,
+        records,
+        dnssec: None,
+        dnssec_error: None,
+    })
+}
+
+/// Query `resolver` for the `record_type` records of `name`, in DNS wire format.
+pub async fn query(name: &str, resolver: &Resolver, record_type: RecordType) -> Result<Answer> {
+    query_with(name, resolver, record_type, QueryFlags::default()).await
+}
+
+/// Query `resolver` for the `record_type` records of `name`, with DNSSEC `flags`.
+pub async fn query_with(
+    name: &str,
+    resolver: &Resolver,
+    record_type: RecordType,
+    flags: QueryFlags,
+) -> Result<Answer> {
+    let message = wire::encode_query(name, record_type, flags)?;
+
+    let mut url = Url::parse(&resolver.url)?;
+    let headers = Headers::new();
+    headers.set("Accept", DNS_MESSAGE)?;
+
+    let init = match resolver.method {
+        DohMethod::Get => {
+            url.query_pairs_mut()
+                .append_pair("dns", &wire::base64url(&message));
+            RequestInit {
+                headers,
+                method: Method::Get,
+                ..Default::default()
+            }
+        }
+        DohMethod::Post => {
+            headers.set("Content-Type", DNS_MESSAGE)?;
+            RequestInit {
+                headers,
+                method: Method::Post,
+                body: Some(js_sys::Uint8Array::from(message.as_slice()).into()),
+                ..Default::default()
+            }
+        }
+    };
+
+    let request = Request::new_with_init(url.as_str(), &init)?;
+    let mut response = Fetch::Request(request).send().await?;
+    if response.status_code() != 200 {
+        return Err(format!("HTTP {}.", response.status_code()).into());
+    }
+
+    Ok(wire::decode_response(&response.bytes().await?)?)
+}
+
+/// Name of a record type, falling back to the generic `TYPEn` format (RFC 3597).
+pub fn type_name(code: u16) -> String {
+    match code {
+        6 => return "SOA".to_string(),
+        43 => return "DS".to_string(),
+        46 => return "RRSIG".to_string(),
+        48 => return "DNSKEY".to_string(),
+        _ => {}
+    }
+
+    RecordType::ALL
+        .into_iter()
+        .find(|record_type| record_type.code() == code)
+        .map_or_else(
+            || format!("TYPE{code}"),
+            |record_type| record_type.name().to_string(),
+        )
+}
+
+/// Text of a `TXT` record, joining the quoted and escaped strings of its presentation format.
+pub fn txt_text(data: &str) -> String {
+    let data = data.trim();
+    if !data.starts_with('"') {
+        return data.to_string();
+    }
+
+    let mut text = String::new();
+    let mut quoted = false;
+    let mut chars = data.chars();
+    while let Some(c) = chars.next() {
+        match c {
+            '"' => quoted = !quoted,
+            '\\' if quoted => text.extend(chars.next()),
+            c if quoted => text.push(c),
+            _ => {}
+        }
+    }
+
+    text
+}
+
+/// Name of a DNS response code, as registered by IANA.
+pub fn rcode_name(rcode: u64) -> String {
+    let name = match rcode {
+        0 => "NOERROR",
+        1 => "FORMERR",
+        2 => "SERVFAIL",
+        3 => "NXDOMAIN",
+        4 => "NOTIMP",
+        5 => "REFUSED",
+        6 => "YXDOMAIN",
+        7 => "YXRRSET",
+        8 => "NXRRSET",
+        9 => "NOTAUTH",
+        10 => "NOTZONE",
+        other => return format!("RCODE{other}"),
+    };
+
+    name.to_string()
+}
@@ -0,0 +1,59 @@
+use futures::future::try_join;
+use serde::{Deserialize, Serialize};
+use worker::Result;
+
+use crate::dns::{self, DnsError, RecordType, Resolver};
+use crate::wire::QueryFlags;
+
+/// Number of subrequests needed by a DNSSEC check.
+pub const SUBREQUESTS: usize = 2;
+
+/// DNSSEC status of a host, as seen by a resolver.
+#[derive(Serialize, Deserialize, Clone)]
+pub struct Dnssec {
+    /// The zone is signed: the answer comes with `RRSIG` records.
+    pub signed: bool,
+    /// The resolver validated the answer (AD flag).
+    pub validated: bool,
+    /// Validation fails: `SERVFAIL` with checking enabled, but an answer with checking disabled.
+    pub failing: bool,
+}
+
+/// Check the DNSSEC status of the `A` records of `host`, querying `resolver` with and without
+/// checking disabled.
+pub async fn check(host: &str, resolver: &Resolver) -> Result<Dnssec> {
+    let query = |checking_disabled| {
+        dns::query_with(
+            host,
+            resolver,
+            RecordType::A,
+            QueryFlags {
+                checking_disabled,
+                dnssec_ok: true,
+            },
+        )
+    };
+    let (checked, unchecked) = try_join(query(false), query(true)).await?;
+
+    Ok(Dnssec {
+        signed: unchecked
+            .records
+            .iter()
+            .any(|record| record.record_type == "RRSIG"),
+        validated: checked.rcode == 0 && checked.authenticated,
+        failing: checked.rcode == 2 && unchecked.rcode == 0,
+    })
+}
+
+/// Explain a `SERVFAIL` of the DNS check: a DNSSEC validation failure, e.g. after a broken key
+/// rollover, rather than an unreachable name server.
+pub async fn diagnose(host: &str, resolver: Option<&Resolver>) -> DnsError {
+    let Some(resolver) = resolver else {
+        return DnsError::Rcode(2);
+    };
+
+    match check(host, resolver).await {
+        Ok(Dnssec { failing: true, .. }) => DnsError::Dnssec,
+        _ => DnsError::Rcode(2),
+    }
+}
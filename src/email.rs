@@ -0,0 +1,630 @@
+use std::collections::HashSet;
+use std::net::{Ipv4Addr, Ipv6Addr};
+
+use futures::future::join_all;
+use serde::{Deserialize, Serialize};
+
+use crate::dns::{self, DnsError, RecordType, Resolver};
+
+/// DKIM selectors tried when none are requested, as used by common mail providers.
+pub const DEFAULT_DKIM_SELECTORS: [&str; 8] = [
+    "default",
+    "google",
+    "selector1",
+    "selector2",
+    "k1",
+    "s1",
+    "s2",
+    "dkim",
+];
+/// SPF evaluation fails past this many DNS lookups (RFC 7208, section 4.6.4).
+const MAX_SPF_LOOKUPS: usize = 10;
+
+/// How well a domain is set up to send and receive mail.
+#[derive(Serialize, Deserialize, Clone)]
+pub struct EmailAudit {
+    /// The registrable domain that was audited.
+    pub domain: String,
+    /// `A` to `F`, from the score.
+    pub grade: String,
+    /// Out of 100: 25 for MX, 25 for SPF, 30 for DMARC and 20 for DKIM, halved on a warning.
+    pub score: u32,
+    pub mx: Check,
+    pub spf: SpfCheck,
+    pub dmarc: DmarcCheck,
+    pub dkim: DkimCheck,
+}
+
+#[derive(Serialize, Deserialize, Clone, Copy, PartialEq, PartialOrd)]
+#[serde(rename_all = "lowercase")]
+pub enum CheckStatus {
+    Pass,
+    Warn,
+    Fail,
+}
+
+/// The outcome of a single check, with the records it looked at.
+#[derive(Serialize, Deserialize, Clone)]
+pub struct Check {
+    pub status: CheckStatus,
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub records: Vec<String>,
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub issues: Vec<String>,
+}
+
+#[derive(Serialize, Deserialize, Clone)]
+pub struct SpfCheck {
+    #[serde(flatten)]
+    pub check: Check,
+    /// DNS lookups needed to evaluate the record, including nested `include` and `redirect`.
+    pub lookups: usize,
+}
+
+#[derive(Serialize, Deserialize, Clone)]
+pub struct DmarcCheck {
+    #[serde(flatten)]
+    pub check: Check,
+    pub policy: Option<String>,
+}
+
+#[derive(Serialize, Deserialize, Clone)]
+pub struct DkimCheck {
+    #[serde(flatten)]
+    pub check: Check,
+    /// Selectors with a published key.
+    pub selectors: Vec<String>,
+}
+
+impl Check {
+    fn new(records: Vec<String>) -> Self {
+        Self {
+            status: CheckStatus::Pass,
+            records,
+            issues: Vec::new(),
+        }
+    }
+
+    fn failed(message: String) -> Self {
+        let mut check = Self::new(Vec::new());
+        check.issue(CheckStatus::Fail, message);
+        check
+    }
+
+    /// Record an issue, lowering the status to `status` if it is worse.
+    fn issue(&mut self, status: CheckStatus, message: impl Into<String>) {
+        if status > self.status {
+            self.status = status;
+        }
+        self.issues.push(message.into());
+    }
+
+    fn points(&self, weight: u32) -> u32 {
+        match self.status {
+            CheckStatus::Pass => weight,
+            CheckStatus::Warn => weight / 2,
+            CheckStatus::Fail => 0,
+        }
+    }
+}
+
+/// Number of DNS queries an audit may need, for each resolver.
+pub fn queries(selectors: Option<&[String]>) -> usize {
+    let selectors = selectors.map_or(DEFAULT_DKIM_SELECTORS.len(), <[String]>::len);
+
+    // MX, SPF, nested SPF lookups, DMARC and DKIM.
+    3 + MAX_SPF_LOOKUPS + selectors
+}
+
+/// Audit the mail setup of the registrable domain of `host`.
+pub async fn audit(host: &str, selectors: Option<&[String]>, resolvers: &[Resolver]) -> EmailAudit {
+    let domain = psl::domain_str(host).unwrap_or(host).to_string();
+    let selectors = selectors.map_or_else(
+        || DEFAULT_DKIM_SELECTORS.map(str::to_string).to_vec(),
+        <[String]>::to_vec,
+    );
+
+    let (mx, spf, dmarc, dkim) = futures::join!(
+        check_mx(&domain, resolvers),
+        check_spf(&domain, resolvers),
+        check_dmarc(&domain, resolvers),
+        check_dkim(&domain, &selectors, resolvers),
+    );
+
+    let score =
+        mx.points(25) + spf.check.points(25) + dmarc.check.points(30) + dkim.check.points(20);
+    let grade = match score {
+        90.. => "A",
+        75.. => "B",
+        60.. => "C",
+        40.. => "D",
+        _ => "F",
+    };
+
+    EmailAudit {
+        domain,
+        grade: grade.to_string(),
+        score,
+        mx,
+        spf,
+        dmarc,
+        dkim,
+    }
+}
+
+async fn check_mx(domain: &str, resolvers: &[Resolver]) -> Check {
+    let records = match lookup(domain, RecordType::Mx, resolvers).await {
+        Ok(records) => records,
+        Err(e) => return Check::failed(e),
+    };
+
+    let mut check = Check::new(records.clone());
+    if records.is_empty() {
+        check.issue(
+            CheckStatus::Fail,
+            "No MX record, the domain cannot receive mail.",
+        );
+    } else if records
+        .iter()
+        .any(|record| record.split_whitespace().nth(1) == Some("."))
+    {
+        check.issue(
+            CheckStatus::Warn,
+            "Null MX record (RFC 7505), the domain accepts no mail.",
+        );
+    }
+
+    check
+}
+
+async fn check_spf(domain: &str, resolvers: &[Resolver]) -> SpfCheck {
+    let records = match spf_records(domain, resolvers).await {
+        Ok(records) => records,
+        Err(e) => {
+            return SpfCheck {
+                check: Check::failed(e),
+                lookups: 0,
+            }
+        }
+    };
+
+    let mut check = Check::new(records.clone());
+    let record = match records.as_slice() {
+        [] => {
+            check.issue(CheckStatus::Fail, "No SPF record.");
+            return SpfCheck { check, lookups: 0 };
+        }
+        [record] => record,
+        _ => {
+            check.issue(
+                CheckStatus::Fail,
+                "Several SPF records, receivers reject them all.",
+            );
+            return SpfCheck { check, lookups: 0 };
+        }
+    };
+
+    let spf = match Spf::parse(record) {
+        Ok(spf) => spf,
+        Err(e) => {
+            check.issue(CheckStatus::Fail, e);
+            return SpfCheck { check, lookups: 0 };
+        }
+    };
+
+    match spf.all {
+        Some('+') => check.issue(
+            CheckStatus::Fail,
+            "`+all` lets anyone send mail as the domain.",
+        ),
+        Some('?') => check.issue(CheckStatus::Warn, "`?all` gives no protection."),
+        None if !spf.redirect => check.issue(
+            CheckStatus::Warn,
+            "No `all` mechanism, other senders are neutral.",
+        ),
+        _ => {}
+    }
+    if spf.ptr {
+        check.issue(
+            CheckStatus::Warn,
+            "The `ptr` mechanism is deprecated and may be ignored.",
+        );
+    }
+
+    // Follow `include` and `redirect` targets, without recursion, until the limit is exceeded.
+    let mut lookups = spf.lookups;
+    let mut pending = spf.targets;
+    let mut visited = HashSet::from([domain.to_string()]);
+    while lookups <= MAX_SPF_LOOKUPS {
+        let Some(target) = pending.pop() else {
+            break;
+        };
+        // Targets with macros depend on the sender and cannot be expanded.
+        if target.contains('%') || !visited.insert(target.clone()) {
+            continue;
+        }
+
+        let nested = match spf_records(&target, resolvers).await {
+            Ok(records) if records.len() == 1 => Spf::parse(&records[0]),
+            Ok(records) if records.is_empty() => Err("no SPF record".to_string()),
+            Ok(_) => Err("several SPF records".to_string()),
+            Err(e) => Err(e),
+        };
+        match nested {
+            Ok(nested) => {
+                lookups += nested.lookups;
+                pending.extend(nested.targets);
+            }
+            Err(e) => check.issue(CheckStatus::Fail, format!("`{target}`: {e}")),
+        }
+    }
+
+    if lookups > MAX_SPF_LOOKUPS {
+        check.issue(
+            CheckStatus::Fail,
+            format!("More than {MAX_SPF_LOOKUPS} DNS lookups, receivers reject the record."),
+        );
+    }
+
+    SpfCheck { check, lookups }
+}
+
+async fn check_dmarc(domain: &str, resolvers: &[Resolver]) -> DmarcCheck {
+    let records = match lookup(&format!("_dmarc.{domain}"), RecordType::Txt, resolvers).await {
+        Ok(records) => records
+            .iter()
+            .map(|record| dns::txt_text(record))
+            .filter(|text| text.starts_with("v=DMARC1"))
+            .collect::<Vec<_>>(),
+        Err(e) => {
+            return DmarcCheck {
+                check: Check::failed(e),
+                policy: None,
+            }
+        }
+    };
+
+    let mut check = Check::new(records.clone());
+    let [record] = records.as_slice() else {
+        let message = match records.len() {
+            0 => "No DMARC record.",
+            _ => "Several DMARC records, receivers ignore them all.",
+        };
+        check.issue(CheckStatus::Fail, message);
+        return DmarcCheck {
+            check,
+            policy: None,
+        };
+    };
+
+    let tags = tags(record);
+    let tag = |name: &str| {
+        tags.iter()
+            .find(|(key, _)| key.eq_ignore_ascii_case(name))
+            .map(|(_, value)| value.as_str())
+    };
+
+    let policy = tag("p").map(str::to_ascii_lowercase);
+    match policy.as_deref() {
+        Some("reject" | "quarantine") => {}
+        Some("none") => check.issue(
+            CheckStatus::Warn,
+            "`p=none` only monitors, spoofed mail is delivered.",
+        ),
+        Some(other) => check.issue(CheckStatus::Fail, format!("Invalid policy `p={other}`.")),
+        None => check.issue(
+            CheckStatus::Fail,
+            "Missing policy, the `p` tag is required.",
+        ),
+    }
+
+    if let Some(pct) = tag("pct").and_then(|pct| pct.parse::<u8>().ok()) {
+        if pct < 100 {
+            check.issue(
+                CheckStatus::Warn,
+                format!("`pct={pct}` only applies the policy to part of the mail."),
+            );
+        }
+    }
+    if tag("rua").is_none() {
+        check.issue(
+            CheckStatus::Warn,
+            "No `rua` tag, no aggregate report is sent.",
+        );
+    }
+
+    DmarcCheck { check, policy }
+}
+
+async fn check_dkim(domain: &str, selectors: &[String], resolvers: &[Resolver]) -> DkimCheck {
+    let keys = join_all(selectors.iter().map(|selector| async move {
+        let name = format!("{selector}._domainkey.{domain}");
+        let key = lookup(&name, RecordType::Txt, resolvers)
+            .await
+            .map(|records| {
+                records
+                    .iter()
+                    .map(|record| dns::txt_text(record))
+                    .find(|text| tags(text).iter().any(|(key, _)| key == "p"))
+            });
+        (selector, key)
+    }))
+    .await;
+
+    let mut check = Check::new(Vec::new());
+    let mut found = Vec::new();
+    for (selector, key) in keys {
+        let key = match key {
+            Ok(Some(key)) => key,
+            Ok(None) => continue,
+            Err(e) => {
+                check.issue(CheckStatus::Warn, e);
+                continue;
+            }
+        };
+
+        if tags(&key)
+            .iter()
+            .any(|(name, value)| name == "p" && value.is_empty())
+        {
+            check.issue(
+                CheckStatus::Warn,
+                format!("The key of selector `{selector}` is revoked."),
+            );
+        } else {
+            found.push(selector.clone());
+        }
+    }
+
+    if found.is_empty() {
+        check.issue(
+            CheckStatus::Warn,
+            "No DKIM key found for the tried selectors, the domain may use another one.",
+        );
+    }
+
+    DkimCheck {
+        check,
+        selectors: found,
+    }
+}
+
+/// The terms of an SPF record that matter to the audit.
+struct Spf {
+    /// DNS lookups of the record itself.
+    lookups: usize,
+    /// `include` and `redirect` targets, which need lookups of their own.
+    targets: Vec<String>,
+    /// Qualifier of the `all` mechanism.
+    all: Option<char>,
+    /// Whether a `redirect` modifier hands the evaluation, and its `all`, to another record.
+    redirect: bool,
+    ptr: bool,
+}
+
+impl Spf {
+    /// Parse an SPF record (RFC 7208, section 12), failing on the first invalid term.
+    fn parse(record: &str) -> Result<Self, String> {
+        let mut terms = record.split_whitespace();
+        if !terms
+            .next()
+            .is_some_and(|version| version.eq_ignore_ascii_case("v=spf1"))
+        {
+            return Err("The record does not start with `v=spf1`.".to_string());
+        }
+
+        let mut spf = Spf {
+            lookups: 0,
+            targets: Vec::new(),
+            all: None,
+            redirect: false,
+            ptr: false,
+        };
+        let invalid = |term: &str| format!("Invalid term `{term}`.");
+
+        for term in terms {
+            // Modifiers are `name=value`, mechanisms may only have a `:` or `/` argument.
+            if let Some((name, value)) = term
+                .split_once('=')
+                .filter(|(name, _)| !name.contains([':', '/']))
+            {
+                if value.is_empty() || !name.starts_with(|c: char| c.is_ascii_alphabetic()) {
+                    return Err(invalid(term));
+                }
+                if name.eq_ignore_ascii_case("redirect") {
+                    spf.lookups += 1;
+                    spf.targets.push(value.to_string());
+                    spf.redirect = true;
+                }
+                continue;
+            }
+
+            let (qualifier, mechanism) = match term.chars().next() {
+                Some(c @ ('+' | '-' | '~' | '?')) => (c, &term[1..]),
+                _ => ('+', term),
+            };
+            let end = mechanism.find([':', '/']).unwrap_or(mechanism.len());
+            let (name, argument) = mechanism.split_at(end);
+            let domain = argument.strip_prefix(':');
+
+            match name.to_ascii_lowercase().as_str() {
+                "all" if argument.is_empty() => spf.all = Some(qualifier),
+                "include" | "exists" if domain.is_some_and(|d| !d.is_empty()) => {
+                    spf.lookups += 1;
+                    if name.eq_ignore_ascii_case("include") {
+                        spf.targets.extend(domain.map(str::to_string));
+                    }
+                }
+                "a" | "mx" => spf.lookups += 1,
+                "ptr" if !argument.contains('/') => {
+                    spf.lookups += 1;
+                    spf.ptr = true;
+                }
+                "ip4" if domain.is_some_and(|d| valid_network::<Ipv4Addr>(d, 32)) => {}
+                "ip6" if domain.is_some_and(|d| valid_network::<Ipv6Addr>(d, 128)) => {}
+                _ => return Err(invalid(term)),
+            }
+        }
+
+        Ok(spf)
+    }
+}
+
+/// Whether `network` is an address with an optional CIDR prefix length of at most `max_prefix`.
+fn valid_network<A: std::str::FromStr>(network: &str, max_prefix: u8) -> bool {
+    let (address, prefix) = match network.split_once('/') {
+        Some((address, prefix)) => (address, Some(prefix)),
+        None => (network, None),
+    };
+
+    address.parse::<A>().is_ok()
+        && prefix.is_none_or(|prefix| prefix.parse::<u8>().is_ok_and(|p| p <= max_prefix))
+}
+
+/// The `name=value` tags of a DMARC or DKIM record.
+fn tags(record: &str) -> Vec<(String, String)> {
+    record
+        .split(';')
+        .filter_map(|tag| tag.split_once('='))
+        .map(|(name, value)| (name.trim().to_string(), value.trim().to_string()))
+        .collect()
+}
+
+/// The SPF records among the `TXT` records of `domain`.
+async fn spf_records(domain: &str, resolvers: &[Resolver]) -> Result<Vec<String>, String> {
+    Ok(lookup(domain, RecordType::Txt, resolvers)
+        .await?
+        .iter()
+        .map(|record| dns::txt_text(record))
+        .filter(|text| is_spf(text))
+        .collect())
+}
+
+/// Whether a `TXT` record is an SPF record: `v=spf1` followed by a space or nothing, so that e.g.
+/// `v=spf10` is not one.
+fn is_spf(text: &str) -> bool {
+    text.get(..6)
+        .is_some_and(|version| version.eq_ignore_ascii_case("v=spf1"))
+        && text[6..].chars().next().is_none_or(|c| c == ' ')
+}
+
+/// The data of the `record_type` records of `name`, none when the name does not exist.
+async fn lookup(
+    name: &str,
+    record_type: RecordType,
+    resolvers: &[Resolver],
+) -> Result<Vec<String>, String> {
+    match dns::lookup(name, record_type, resolvers).await {
+        Ok((_, answer)) => Ok(answer
+            .records
+            .into_iter()
+            .filter(|record| record.record_type == record_type.name())
+            .map(|record| record.data)
+            .collect()),
+        Err(DnsError::Rcode(3)) => Ok(Vec::new()),
+        Err(DnsError::Rcode(rcode)) => Err(format!(
+            "Lookup of `{name}` failed with {}.",
+            dns::rcode_name(rcode)
+        )),
+        Err(e) => Err(format!("Lookup of `{name}` failed: {e}")),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parses_spf_records() {
+        let spf = Spf::parse(
+            "v=spf1 ip4:192.0.2.0/24 ip6:2001:db8::/32 a mx:mail.example.com \
+             include:_spf.google.com exists:%{i}.example.com ~all",
+        )
+        .unwrap();
+        assert_eq!(spf.lookups, 4);
+        assert_eq!(spf.targets, ["_spf.google.com"]);
+        assert_eq!(spf.all, Some('~'));
+        assert!(!spf.redirect && !spf.ptr);
+
+        let spf = Spf::parse("V=SPF1 -PTR:example.com Redirect=_spf.example.com").unwrap();
+        assert_eq!(spf.lookups, 2);
+        assert_eq!(spf.targets, ["_spf.example.com"]);
+        assert_eq!(spf.all, None);
+        assert!(spf.redirect && spf.ptr);
+
+        let spf = Spf::parse("v=spf1 include:a.example.com include:b.example.com").unwrap();
+        assert_eq!(spf.targets, ["a.example.com", "b.example.com"]);
+        assert!(!spf.redirect);
+
+        let spf = Spf::parse("v=spf1 exp=explain.example.com -all").unwrap();
+        assert_eq!(spf.lookups, 0);
+        assert_eq!(spf.all, Some('-'));
+    }
+
+    #[test]
+    fn rejects_invalid_spf_records() {
+        for (record, error) in [
+            ("", "The record does not start with `v=spf1`."),
+            ("v=spf10 -all", "The record does not start with `v=spf1`."),
+            (
+                "v=spf1 ip4:192.0.2.0/33",
+                "Invalid term `ip4:192.0.2.0/33`.",
+            ),
+            ("v=spf1 ip4:2001:db8::1", "Invalid term `ip4:2001:db8::1`."),
+            (
+                "v=spf1 ip6:2001:db8::/129",
+                "Invalid term `ip6:2001:db8::/129`.",
+            ),
+            ("v=spf1 include:", "Invalid term `include:`."),
+            ("v=spf1 all:example.com", "Invalid term `all:example.com`."),
+            ("v=spf1 redirect=", "Invalid term `redirect=`."),
+            ("v=spf1 1x=y", "Invalid term `1x=y`."),
+            ("v=spf1 allow", "Invalid term `allow`."),
+        ] {
+            assert_eq!(Spf::parse(record).err().as_deref(), Some(error), "{record}");
+        }
+    }
+
+    #[test]
+    fn recognizes_spf_records() {
+        assert!(is_spf("v=spf1"));
+        assert!(is_spf("v=spf1 -all"));
+        assert!(is_spf("V=SPF1 ~all"));
+        assert!(!is_spf("v=spf10 -all"));
+        assert!(!is_spf("v=spf1-all"));
+        assert!(!is_spf("v=spf"));
+        assert!(!is_spf("google-site-verification=abc"));
+        assert!(!is_spf("é=spf1 -all"));
+    }
+
+    #[test]
+    fn parses_tags() {
+        let tags = tags(" v=DMARC1; p=reject ;rua=mailto:dmarc@example.com; pct=50;");
+        let tags: Vec<(&str, &str)> = tags
+            .iter()
+            .map(|(name, value)| (name.as_str(), value.as_str()))
+            .collect();
+        assert_eq!(
+            tags,
+            [
+                ("v", "DMARC1"),
+                ("p", "reject"),
+                ("rua", "mailto:dmarc@example.com"),
+                ("pct", "50"),
+            ]
+        );
+    }
+
+    #[test]
+    fn parses_tags_with_empty_or_missing_values() {
+        let tags = super::tags("v=DKIM1; k=rsa; p=; t");
+        assert_eq!(tags.last(), Some(&("p".to_string(), String::new())));
+        assert_eq!(tags.len(), 3);
+
+        let tags = super::tags("v=DKIM1; p=MIGf+/A==");
+        assert_eq!(tags[1], ("p".to_string(), "MIGf+/A==".to_string()));
+
+        assert!(super::tags("").is_empty());
+        assert!(super::tags("no tags here").is_empty());
+    }
+}
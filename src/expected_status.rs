@@ -0,0 +1,168 @@
+use std::fmt;
+use std::str::FromStr;
+
+use serde::de::{self, Visitor};
+use serde::{Deserialize, Deserializer, Serialize, Serializer};
+
+/// Status codes considered `UP` when a request does not set `expected_status`.
+pub const DEFAULT_EXPECTED_STATUS: [StatusRule; 1] = [StatusRule::Range(200, 399)];
+
+/// A rule of the `expected_status` option, matching one or more HTTP status codes.
+#[derive(Clone, Copy)]
+pub enum StatusRule {
+    /// A single status code, e.g. `401`.
+    Code(u16),
+    /// An inclusive range of status codes, e.g. `200-299`.
+    Range(u16, u16),
+    /// A whole class of status codes, e.g. `2xx`.
+    Class(u16),
+}
+
+impl StatusRule {
+    pub fn matches(self, status_code: u16) -> bool {
+        match self {
+            StatusRule::Code(code) => status_code == code,
+            StatusRule::Range(start, end) => (start..=end).contains(&status_code),
+            StatusRule::Class(class) => status_code / 100 == class,
+        }
+    }
+}
+
+impl FromStr for StatusRule {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let s = s.trim();
+        let invalid = || format!("Invalid expected status `{s}`.");
+        let code = |s: &str| {
+            s.trim()
+                .parse::<u16>()
+                .ok()
+                .filter(|code| (100..600).contains(code))
+                .ok_or_else(invalid)
+        };
+
+        if let Some(class) = s.strip_suffix("xx").or_else(|| s.strip_suffix("XX")) {
+            return match class.parse::<u16>() {
+                Ok(class @ 1..=5) => Ok(StatusRule::Class(class)),
+                _ => Err(invalid()),
+            };
+        }
+
+        match s.split_once('-') {
+            Some((start, end)) => {
+                let (start, end) = (code(start)?, code(end)?);
+                if start > end {
+                    return Err(invalid());
+                }
+                Ok(StatusRule::Range(start, end))
+            }
+            None => code(s).map(StatusRule::Code),
+        }
+    }
+}
+
+impl fmt::Display for StatusRule {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            StatusRule::Code(code) => write!(f, "{code}"),
+            StatusRule::Range(start, end) => write!(f, "{start}-{end}"),
+            StatusRule::Class(class) => write!(f, "{class}xx"),
+        }
+    }
+}
+
+impl Serialize for StatusRule {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        serializer.collect_str(self)
+    }
+}
+
+impl<'de> Deserialize<'de> for StatusRule {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        struct StatusRuleVisitor;
+
+        impl Visitor<'_> for StatusRuleVisitor {
+            type Value = StatusRule;
+
+            fn expecting(&self, f: &mut fmt::Formatter) -> fmt::Result {
+                f.write_str("a status code, a range like `200-299` or a class like `2xx`")
+            }
+
+            fn visit_u64<E: de::Error>(self, v: u64) -> Result<StatusRule, E> {
+                self.visit_str(&v.to_string())
+            }
+
+            fn visit_str<E: de::Error>(self, v: &str) -> Result<StatusRule, E> {
+                v.parse().map_err(E::custom)
+            }
+        }
+
+        deserializer.deserialize_any(StatusRuleVisitor)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn parse(s: &str) -> Result<String, String> {
+        s.parse::<StatusRule>().map(|rule| rule.to_string())
+    }
+
+    #[test]
+    fn parses_codes_ranges_and_classes() {
+        assert_eq!(parse("401"), Ok("401".to_string()));
+        assert_eq!(parse(" 200 - 299 "), Ok("200-299".to_string()));
+        assert_eq!(parse("301-301"), Ok("301-301".to_string()));
+        assert_eq!(parse("2xx"), Ok("2xx".to_string()));
+        assert_eq!(parse("5XX"), Ok("5xx".to_string()));
+    }
+
+    #[test]
+    fn rejects_invalid_rules() {
+        for s in [
+            "",
+            "abc",
+            "99",
+            "600",
+            "-200",
+            "200-",
+            "299-200",
+            "200-700",
+            "0xx",
+            "6xx",
+            "xx",
+            "2x",
+            "200-299-399",
+        ] {
+            assert_eq!(
+                parse(s),
+                Err(format!("Invalid expected status `{}`.", s.trim()))
+            );
+        }
+    }
+
+    #[test]
+    fn matches_status_codes() {
+        let range: StatusRule = "200-299".parse().unwrap();
+        assert!(range.matches(200) && range.matches(299));
+        assert!(!range.matches(199) && !range.matches(300));
+
+        let class: StatusRule = "4xx".parse().unwrap();
+        assert!(class.matches(400) && class.matches(499));
+        assert!(!class.matches(500));
+
+        let code: StatusRule = "401".parse().unwrap();
+        assert!(code.matches(401) && !code.matches(403));
+    }
+
+    #[test]
+    fn deserializes_numbers_and_strings() {
+        let rules: Vec<StatusRule> = serde_json::from_str(r#"[404, "2xx", "300-302"]"#).unwrap();
+        let rules: Vec<String> = rules.iter().map(ToString::to_string).collect();
+        assert_eq!(rules, ["404", "2xx", "300-302"]);
+
+        assert!(serde_json::from_str::<StatusRule>("700").is_err());
+    }
+}
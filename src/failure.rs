@@ -0,0 +1,135 @@
+use serde::{Deserialize, Serialize};
+
+/// Machine-readable cause of a `DOWN` probe.
+#[derive(Serialize, Deserialize, Clone, Copy, PartialEq)]
+#[serde(rename_all = "SCREAMING_SNAKE_CASE")]
+pub enum FailureReason {
+    DnsNxdomain,
+    DnsServfail,
+    DnsNoRecords,
+    DnsRecordMismatch,
+    DnssecFailure,
+    ConnectionRefused,
+    ConnectionReset,
+    TlsError,
+    Timeout,
+    FetchError,
+    TooManyRedirects,
+    RedirectLoop,
+    RedirectAssertion,
+    Http4xx,
+    Http5xx,
+    Cloudflare52x,
+    UnexpectedStatus,
+    BodyAssertion,
+    JsonAssertion,
+    HealthCheckFailed,
+}
+
+impl FailureReason {
+    /// Classify the error message of a failed fetch.
+    pub fn from_fetch_error(message: &str) -> Self {
+        let message = message.to_ascii_lowercase();
+        let mentions = |words: &[&str]| words.iter().any(|word| message.contains(word));
+
+        // Only a name that does not exist is NXDOMAIN, a failed or timed out lookup may succeed
+        // later.
+        let dns = mentions(&["dns", "getaddrinfo", "resolve", "lookup"]);
+        let not_found = mentions(&[
+            "not found",
+            "does not exist",
+            "no such host",
+            "unknown host",
+        ]);
+
+        if mentions(&["servfail", "eai_again"]) {
+            FailureReason::DnsServfail
+        } else if mentions(&["nxdomain", "enotfound", "name_not_resolved"]) || (dns && not_found) {
+            FailureReason::DnsNxdomain
+        } else if dns {
+            FailureReason::DnsServfail
+        } else if mentions(&["refused"]) {
+            FailureReason::ConnectionRefused
+        } else if mentions(&["reset", "connection lost", "disconnected"]) {
+            FailureReason::ConnectionReset
+        } else if mentions(&["tls", "ssl", "certificate", "handshake"]) {
+            FailureReason::TlsError
+        } else if mentions(&["timed out", "timeout"]) {
+            FailureReason::Timeout
+        } else {
+            FailureReason::FetchError
+        }
+    }
+
+    /// Classify an HTTP status code that is not expected.
+    pub fn from_status(status_code: u16) -> Self {
+        match status_code {
+            520..=527 | 530 => FailureReason::Cloudflare52x,
+            400..=499 => FailureReason::Http4xx,
+            500..=599 => FailureReason::Http5xx,
+            _ => FailureReason::UnexpectedStatus,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn classifies_fetch_errors() {
+        let cases = [
+            ("DNS lookup failed: NXDOMAIN", FailureReason::DnsNxdomain),
+            (
+                "getaddrinfo ENOTFOUND example.invalid",
+                FailureReason::DnsNxdomain,
+            ),
+            ("net::ERR_NAME_NOT_RESOLVED", FailureReason::DnsNxdomain),
+            ("DNS: host not found", FailureReason::DnsNxdomain),
+            ("DNS lookup timed out", FailureReason::DnsServfail),
+            ("DNS resolution failed", FailureReason::DnsServfail),
+            (
+                "getaddrinfo EAI_AGAIN example.com",
+                FailureReason::DnsServfail,
+            ),
+            ("SERVFAIL", FailureReason::DnsServfail),
+            ("Connection refused", FailureReason::ConnectionRefused),
+            ("Network connection lost.", FailureReason::ConnectionReset),
+            ("ECONNRESET", FailureReason::ConnectionReset),
+            ("TLS handshake failed", FailureReason::TlsError),
+            ("SSL certificate has expired", FailureReason::TlsError),
+            ("The operation timed out", FailureReason::Timeout),
+            ("internal error", FailureReason::FetchError),
+        ];
+
+        for (message, reason) in cases {
+            assert!(
+                FailureReason::from_fetch_error(message) == reason,
+                "{message}"
+            );
+        }
+    }
+
+    #[test]
+    fn classifies_status_codes() {
+        let cases = [
+            (404, FailureReason::Http4xx),
+            (429, FailureReason::Http4xx),
+            (500, FailureReason::Http5xx),
+            (503, FailureReason::Http5xx),
+            (520, FailureReason::Cloudflare52x),
+            (527, FailureReason::Cloudflare52x),
+            (528, FailureReason::Http5xx),
+            (530, FailureReason::Cloudflare52x),
+            (200, FailureReason::UnexpectedStatus),
+            (302, FailureReason::UnexpectedStatus),
+        ];
+
+        for (status_code, reason) in cases {
+            assert!(
+                FailureReason::from_status(status_code) == reason,
+                "{status_code}"
+            );
+        }
+    }
+}
@@ -0,0 +1,88 @@
+use std::collections::BTreeMap;
+
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+
+use crate::Status;
+
+/// Media type of the [Health Check Response Format for HTTP APIs][draft] draft.
+///
+/// [draft]: https://datatracker.ietf.org/doc/html/draft-inadarei-api-health-check
+pub const HEALTH_JSON: &str = "application/health+json";
+
+/// Body of an `application/health+json` response.
+#[derive(Deserialize)]
+struct HealthResponse {
+    status: String,
+    output: Option<String>,
+    #[serde(default)]
+    checks: BTreeMap<String, Vec<CheckResponse>>,
+}
+
+#[derive(Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct CheckResponse {
+    component_id: Option<String>,
+    component_type: Option<String>,
+    observed_value: Option<Value>,
+    observed_unit: Option<String>,
+    status: Option<String>,
+    output: Option<String>,
+}
+
+/// A component-level check reported by a health endpoint.
+#[derive(Serialize, Deserialize, Clone)]
+pub struct HealthCheck {
+    /// Key of the check, usually `component:measurement`.
+    pub name: String,
+    pub component_id: Option<String>,
+    pub component_type: Option<String>,
+    pub status: Option<Status>,
+    pub observed_value: Option<Value>,
+    pub observed_unit: Option<String>,
+    pub output: Option<String>,
+}
+
+/// Overall status and component checks of a health endpoint.
+pub struct HealthReport {
+    pub status: Option<Status>,
+    pub output: Option<String>,
+    pub checks: Vec<HealthCheck>,
+}
+
+/// Map a `pass`, `warn` or `fail` health status (or one of their aliases) to a probe status.
+fn probe_status(status: &str) -> Option<Status> {
+    match status.to_ascii_lowercase().as_str() {
+        "pass" | "ok" | "up" => Some(Status::Up),
+        "warn" => Some(Status::Degraded),
+        "fail" | "error" | "down" => Some(Status::Down),
+        _ => None,
+    }
+}
+
+/// Parse an `application/health+json` body, `None` if it is not one.
+pub fn parse(body: &str) -> Option<HealthReport> {
+    let response = serde_json::from_str::<HealthResponse>(body).ok()?;
+
+    let checks = response
+        .checks
+        .into_iter()
+        .flat_map(|(name, checks)| {
+            checks.into_iter().map(move |check| HealthCheck {
+                name: name.clone(),
+                component_id: check.component_id,
+                component_type: check.component_type,
+                status: check.status.as_deref().and_then(probe_status),
+                observed_value: check.observed_value,
+                observed_unit: check.observed_unit,
+                output: check.output,
+            })
+        })
+        .collect();
+
+    Some(HealthReport {
+        status: probe_status(&response.status),
+        output: response.output,
+        checks,
+    })
+}
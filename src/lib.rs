@@ -1,31 +1,389 @@
+use std::cell::{Cell, RefCell};
+use std::collections::{BTreeMap, HashSet};
 use std::time::Duration;
 
 use futures::future::Either;
-use futures::pin_mut;
-use serde::{Deserialize, Serialize};
+use futures::stream::FuturesUnordered;
+use futures::{pin_mut, StreamExt};
+use serde::de::{DeserializeOwned, IntoDeserializer};
+use serde::{Deserialize, Deserializer, Serialize};
 use worker::*;
 
-#[derive(Deserialize)]
+use assertions::{BodyAssertions, JsonAssertion};
+use cloudflare::CloudflareError;
+use dns::{DnsError, DnsRecord, DnsReport, DohMethod, RecordType, Resolver};
+use email::EmailAudit;
+use expected_status::{StatusRule, DEFAULT_EXPECTED_STATUS};
+use failure::FailureReason;
+use health::HealthCheck;
+use propagation::Propagation;
+use records::RecordAssertion;
+use redirect::{RedirectHop, RedirectMode};
+use request::{ProbeMethod, ProbeRequest};
+use retry::{Attempt, RetryClass, RetryPolicy};
+
+mod assertions;
+mod blocking;
+mod cloudflare;
+mod dns;
+mod dnssec;
+mod email;
+mod expected_status;
+mod failure;
+mod health;
+mod placeholder;
+mod propagation;
+mod records;
+mod redirect;
+mod request;
+mod retry;
+mod wire;
+
+#[derive(Deserialize, Serialize)]
 struct InputUrl {
+    #[serde(skip_serializing)]
     url: String,
+    #[serde(default, deserialize_with = "comma_separated")]
+    probes: Option<Vec<Rung>>,
+    #[serde(default)]
+    mode: LadderMode,
+    timeout_ms: Option<u64>,
+    #[serde(default, deserialize_with = "comma_separated")]
+    expected_status: Option<Vec<StatusRule>>,
+    #[serde(default, deserialize_with = "one_or_many")]
+    must_contain: Option<Vec<String>>,
+    #[serde(default, deserialize_with = "one_or_many")]
+    must_not_contain: Option<Vec<String>>,
+    #[serde(default, deserialize_with = "one_or_many")]
+    must_match: Option<Vec<String>>,
+    #[serde(default, deserialize_with = "one_or_many")]
+    must_not_match: Option<Vec<String>>,
+    json_assertions: Option<Vec<JsonAssertion>>,
+    degraded_response_time_ms: Option<u64>,
+    degraded_ttfb_ms: Option<u64>,
+    method: Option<ProbeMethod>,
+    #[serde(serialize_with = "request::digest_headers")]
+    headers: Option<BTreeMap<String, String>>,
+    #[serde(serialize_with = "request::digest_body")]
+    body: Option<String>,
+    user_agent: Option<String>,
+    redirect: Option<RedirectMode>,
+    max_redirects: Option<usize>,
+    expected_final_url: Option<String>,
+    expected_final_host: Option<String>,
+    retries: Option<u32>,
+    retry_backoff_ms: Option<u64>,
+    #[serde(default, deserialize_with = "comma_separated")]
+    retry_on: Option<Vec<RetryClass>>,
+    propagation: Option<RecordType>,
+    #[serde(default, deserialize_with = "comma_separated")]
+    propagation_resolvers: Option<Vec<String>>,
+    dns_records: Option<Vec<RecordAssertion>>,
+    dnssec: Option<bool>,
+    email_audit: Option<bool>,
+    #[serde(default, deserialize_with = "comma_separated")]
+    dkim_selectors: Option<Vec<String>>,
+}
+
+/// A step of the probe ladder, each one probing a variant of the requested URL.
+#[derive(Deserialize, Serialize, Clone, Copy)]
+#[serde(rename_all = "kebab-case")]
+enum Rung {
+    Exact,
+    Host,
+    Www,
+    #[serde(alias = "apex")]
+    Domain,
+    HttpScheme,
+}
+
+const DEFAULT_LADDER: [Rung; 3] = [Rung::Exact, Rung::Host, Rung::Domain];
+
+impl Rung {
+    fn name(self) -> &'static str {
+        match self {
+            Rung::Exact => "exact",
+            Rung::Host => "host",
+            Rung::Www => "www",
+            Rung::Domain => "domain",
+            Rung::HttpScheme => "http-scheme",
+        }
+    }
+
+    /// URL probed by this rung, or `None` when the rung does not apply to `target_url`.
+    fn url(self, target_url: &Url) -> Option<String> {
+        let scheme = target_url.scheme();
+        let host = target_url.host_str()?;
+
+        match self {
+            // Skipped when the requested URL is just the host root, the `host` rung covers it.
+            Rung::Exact => {
+                let mut exact_url = target_url.clone();
+                exact_url.set_fragment(None);
+                (exact_url.as_str() != format!("{scheme}://{host}/")).then(|| exact_url.to_string())
+            }
+            Rung::Host => Some(format!("{scheme}://{host}")),
+            Rung::Www => psl::domain_str(target_url.domain()?)
+                .map(|domain| format!("{scheme}://www.{domain}")),
+            Rung::Domain => {
+                psl::domain_str(target_url.domain()?).map(|domain| format!("{scheme}://{domain}"))
+            }
+            Rung::HttpScheme => Some(format!("http://{host}")),
+        }
+    }
+}
+
+#[derive(Deserialize, Serialize, Clone, Copy, Default, PartialEq)]
+#[serde(rename_all = "kebab-case")]
+enum LadderMode {
+    /// Stop at the first rung that is `UP`.
+    #[default]
+    FirstUp,
+    /// Probe every rung.
+    All,
+}
+
+enum Input {
+    Single(Box<InputUrl>),
+    /// Unparsed items, so that an invalid one only fails its own position.
+    Batch(Vec<serde_json::Value>),
+}
+
+impl Input {
+    /// Parse a POST body, an array being a batch. Not an untagged enum, whose error would hide
+    /// which field is invalid.
+    fn from_json(body: serde_json::Value) -> serde_json::Result<Self> {
+        match body {
+            serde_json::Value::Array(items) => Ok(Input::Batch(items)),
+            body => serde_json::from_value(body).map(Input::Single),
+        }
+    }
+}
+
+/// Parse a batch item, failing with its error item, which has the item's `url` if any.
+fn batch_input(item: serde_json::Value) -> std::result::Result<InputUrl, BatchItem> {
+    let requested_url = item
+        .get("url")
+        .and_then(serde_json::Value::as_str)
+        .unwrap_or_default()
+        .to_string();
+
+    serde_json::from_value(item).map_err(|e| BatchItem::Err {
+        requested_url,
+        error: e.to_string(),
+        status_code: 400,
+    })
 }
 
-#[derive(Serialize, Clone)]
+#[derive(Serialize, Deserialize, Clone, Default)]
 struct ProbeResult {
     #[serde(rename = "type")]
     probe_type: String,
     url: String,
-    status: String,
+    status: Status,
     status_code: Option<u16>,
     status_text: String,
+    /// Why the probe is `DOWN`, `None` otherwise.
+    failure_reason: Option<FailureReason>,
+    /// Time until the whole response, or its first `max_body_bytes`, was received, or until the
+    /// probe failed.
+    response_time_ms: u64,
+    /// Time until the response headers were received, if they ever were.
+    ttfb_ms: Option<u64>,
+    timeout_ms: u64,
+    /// The `expected_status` rule the status code matched, if any.
+    matched_status: Option<String>,
+    /// Path of the JSON field whose assertion failed, if any.
+    failed_field: Option<String>,
+    /// Vendor of the challenge or block page answered instead of the page, if any.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    blocked_by: Option<String>,
+    /// Kind of parked domain or placeholder page answered instead of a real site, if any.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    placeholder: Option<String>,
+    /// Cloudflare error returned in front of the origin, if any.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    cloudflare: Option<CloudflareError>,
+    /// Component checks of an `application/health+json` response.
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    checks: Vec<HealthCheck>,
+    /// Redirects followed hop by hop before the final response.
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    redirects: Vec<RedirectHop>,
+    /// URL of the final response, when redirects are followed hop by hop.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    final_url: Option<String>,
+    /// Every attempt of the probe, when it was retried.
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    attempts: Vec<Attempt>,
+    /// Records found by a DNS record probe.
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    records: Vec<DnsRecord>,
+}
+
+/// Outcome of a probe, serialized as `UP`, `DEGRADED`, `BLOCKED`, `PARKED`, `PLACEHOLDER` or
+/// `DOWN`.
+#[derive(Serialize, Deserialize, Clone, Copy, PartialEq, Default)]
+#[serde(rename_all = "UPPERCASE")]
+enum Status {
+    Up,
+    /// Reachable, but slow, rate limited or reporting a `warn` health status.
+    Degraded,
+    /// Answered with a bot challenge, captcha or WAF block instead of the page.
+    Blocked,
+    /// Answered with a registrar or marketplace parking page.
+    Parked,
+    /// Answered with a coming soon, default web server or suspended account page.
+    Placeholder,
+    #[default]
+    Down,
 }
 
-#[derive(Serialize, Clone)]
+impl Status {
+    /// Whether the target is reachable, which stops a `first-up` ladder.
+    fn is_up(self) -> bool {
+        matches!(self, Status::Up | Status::Degraded)
+    }
+}
+
+#[derive(Serialize, Deserialize, Clone)]
 struct FinalResponse {
     requested_url: String,
+    request: ProbeRequest,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    dns: Option<DnsReport>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    propagation: Option<Propagation>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    email: Option<EmailAudit>,
     results: Vec<ProbeResult>,
 }
 
+impl FinalResponse {
+    /// Only a target found up by its ladder is cached, a failure may be transient and is checked
+    /// again. DNS record results do not count, they are up even when the site is not.
+    fn is_cacheable(&self) -> bool {
+        self.results
+            .iter()
+            .any(|result| result.probe_type != records::PROBE_TYPE && result.status.is_up())
+    }
+
+    fn cache_control(&self, config: &Config) -> String {
+        if self.is_cacheable() {
+            format!("max-age={}", config.cache_ttl)
+        } else {
+            "no-store".to_string()
+        }
+    }
+}
+
+#[derive(Serialize)]
+#[serde(untagged)]
+enum BatchItem {
+    Ok {
+        cache: &'static str,
+        #[serde(flatten)]
+        response: Box<FinalResponse>,
+    },
+    Err {
+        requested_url: String,
+        error: String,
+        status_code: u16,
+    },
+}
+
+/// Settings read from the environment once per request.
+struct Config {
+    cache_ttl: u32,
+    probe_timeout_ms: u64,
+    max_probe_timeout_ms: u64,
+    max_body_bytes: usize,
+    doh_resolvers: Vec<Resolver>,
+    doh_method: DohMethod,
+}
+
+impl Config {
+    fn from_env(env: &Env) -> Self {
+        let doh_method = env
+            .var("DOH_METHOD")
+            .ok()
+            .and_then(|method| DohMethod::parse(&method.to_string()))
+            .unwrap_or(DohMethod::Get);
+
+        let configured = env.var("DOH_RESOLVERS").ok().map(|var| var.to_string());
+        let mut doh_resolvers: Vec<String> = configured
+            .iter()
+            .flat_map(|resolvers| resolvers.split(','))
+            .map(str::trim)
+            .filter(|resolver| !resolver.is_empty())
+            .map(str::to_string)
+            .collect();
+
+        // An empty variable would leave no resolver to check the host with.
+        if doh_resolvers.is_empty() {
+            if configured.is_some() {
+                console_log!(
+                    "DOH_RESOLVERS has no resolver, using {}",
+                    dns::DEFAULT_RESOLVER
+                );
+            }
+            doh_resolvers.push(dns::DEFAULT_RESOLVER.to_string());
+        }
+
+        Self {
+            cache_ttl: env_number(env, "CACHE_TTL_SECONDS").unwrap_or(600),
+            probe_timeout_ms: env_number::<u64>(env, "PROBE_TIMEOUT_SECONDS")
+                .unwrap_or(60)
+                .saturating_mul(1000),
+            max_probe_timeout_ms: env_number(env, "MAX_PROBE_TIMEOUT_MS").unwrap_or(60_000),
+            max_body_bytes: env_number(env, "MAX_BODY_BYTES").unwrap_or(256 * 1024),
+            doh_resolvers: doh_resolvers
+                .into_iter()
+                .map(|url| Resolver {
+                    url,
+                    method: doh_method,
+                })
+                .collect(),
+            doh_method,
+        }
+    }
+}
+
+/// Per-target settings shared by every probe of the ladder.
+struct ProbeSettings {
+    /// The requested URL, whose origin alone receives the sensitive headers.
+    target_url: Url,
+    method: Method,
+    headers: Headers,
+    body: Option<String>,
+    max_redirects: Option<usize>,
+    timeout_ms: u64,
+    max_body_bytes: usize,
+    body_assertions: BodyAssertions,
+    retry: RetryPolicy,
+}
+
+/// An error that fails a single target, returned with `status` as its HTTP status code.
+struct TargetError {
+    message: String,
+    status: u16,
+}
+
+impl TargetError {
+    fn new(message: impl Into<String>, status: u16) -> Self {
+        Self {
+            message: message.into(),
+            status,
+        }
+    }
+}
+
+impl From<Error> for TargetError {
+    fn from(e: Error) -> Self {
+        Self::new(e.to_string(), 500)
+    }
+}
+
 #[event(fetch)]
 async fn fetch(mut req: Request, env: Env, ctx: Context) -> Result<Response> {
     console_error_panic_hook::set_once();
@@ -37,182 +395,976 @@ async fn fetch(mut req: Request, env: Env, ctx: Context) -> Result<Response> {
         return Response::error("Unauthorized", 401);
     };
 
-    let target_url = match req.method() {
-        Method::Post => req.json::<InputUrl>().await,
-        Method::Get => req.query::<InputUrl>(),
+    let input = match req.method() {
+        Method::Post => req
+            .json::<serde_json::Value>()
+            .await
+            .and_then(|body| Ok(Input::from_json(body)?)),
+        Method::Get => req
+            .query::<InputUrl>()
+            .map(|input| Input::Single(Box::new(input))),
         _ => return Response::error("Method not allowed. Use GET or POST.", 405),
     };
 
-    let mut target_url = match target_url {
-        Ok(input_url) => input_url.url,
+    let input = match input {
+        Ok(input) => input,
         Err(e) => return Response::error(e.to_string(), 400),
     };
 
+    let config = Config::from_env(&env);
+
+    let inputs = match input {
+        Input::Single(input) => {
+            let (response, cache_status) = match check_target(&input, &config, &ctx, None).await {
+                Ok(checked) => checked,
+                Err(e) => return Response::error(e.message, e.status),
+            };
+
+            let headers = Headers::new();
+            headers.set("Cache-Control", &response.cache_control(&config))?;
+            headers.set("X-Worker-Cache", cache_status)?;
+
+            return Response::builder()
+                .with_headers(headers)
+                .from_json(&response);
+        }
+        Input::Batch(inputs) => inputs,
+    };
+
+    // Shared by the targets so the batch never exceeds the Worker subrequest limit.
+    let budget = Cell::new(env_number(&env, "SUBREQUEST_BUDGET").unwrap_or(50));
+    let concurrency: usize = env_number(&env, "BATCH_CONCURRENCY").unwrap_or(6);
+
+    let items = futures::stream::iter(inputs.into_iter().map(|item| {
+        let (config, ctx, budget) = (&config, &ctx, &budget);
+        async move {
+            let input = match batch_input(item) {
+                Ok(input) => input,
+                Err(item) => return item,
+            };
+
+            match check_target(&input, config, ctx, Some(budget)).await {
+                Ok((response, cache)) => BatchItem::Ok {
+                    cache,
+                    response: Box::new(response),
+                },
+                Err(e) => BatchItem::Err {
+                    requested_url: input.url,
+                    error: e.message,
+                    status_code: e.status,
+                },
+            }
+        }
+    }))
+    .buffered(concurrency.max(1))
+    .collect::<Vec<_>>()
+    .await;
+
+    Response::from_json(&items)
+}
+
+/// Probe a single target, returning its result and whether it was served from the cache.
+///
+/// On a cache miss, the worst-case cost of the target is reserved from the `budget` of subrequests
+/// left, if any, before anything is fetched.
+async fn check_target(
+    input: &InputUrl,
+    config: &Config,
+    ctx: &Context,
+    budget: Option<&Cell<usize>>,
+) -> std::result::Result<(FinalResponse, &'static str), TargetError> {
+    let mut target_url = input.url.clone();
+
     if !target_url.starts_with("http") {
         target_url = format!("https://{target_url}");
     }
 
-    let target_url = Url::parse(&target_url)?;
-    let cache_key = target_url.to_string();
+    let target_url = Url::parse(&target_url).map_err(|e| TargetError::new(e.to_string(), 400))?;
+    let cache_key = cache_key(&target_url, input)?;
 
     let cache = Cache::default();
-    if let Ok(Some(cached)) = cache.get(&cache_key, false).await {
-        let new_headers = cached.headers().clone();
-        new_headers.set("X-Worker-Cache", "HIT")?;
-        return Ok(cached.with_headers(new_headers));
+    if let Ok(Some(mut cached)) = cache.get(&cache_key, false).await {
+        if let Ok(response) = cached.json::<FinalResponse>().await {
+            return Ok((response, "HIT"));
+        }
     }
 
-    let mut unique_target = std::collections::HashSet::new();
-    let mut probes: Vec<(String, String)> = Vec::new();
+    if let Some(budget) = budget {
+        let left = budget
+            .get()
+            .checked_sub(subrequest_cost(input, config))
+            .ok_or_else(|| TargetError::new("Subrequest budget exhausted.", 429))?;
+        budget.set(left);
+    }
 
     let Some(host) = target_url.host_str() else {
-        return Err(Error::from("Host is missing."));
+        return Err(TargetError::new("Host is missing.", 400));
+    };
+
+    let dns = match dns::resolve(host, &config.doh_resolvers).await {
+        Err(DnsError::Rcode(2)) => Err(dnssec::diagnose(host, config.doh_resolvers.first()).await),
+        dns => dns,
     };
 
-    if let Ok(status) = check_domain(host).await {
-        if status != 0 {
-            return Response::error(
-                format!("Request does not pass domain check [{status}]."),
-                400,
-            );
+    // A host that does not resolve is not probed, but its DNS record probes still run to tell
+    // which records are missing.
+    let mut dns = match dns {
+        Ok(dns) => Some(dns),
+        Err(_) if input.dns_records.as_ref().is_some_and(|r| !r.is_empty()) => None,
+        Err(e) => return Err(TargetError::new(e.to_string(), e.status())),
+    };
+
+    if let (Some(dns), Some(true)) = (&mut dns, input.dnssec) {
+        let resolver = config
+            .doh_resolvers
+            .iter()
+            .find(|resolver| resolver.url == dns.resolver);
+        if let Some(resolver) = resolver {
+            match dnssec::check(host, resolver).await {
+                Ok(status) => dns.dnssec = Some(status),
+                Err(e) => dns.dnssec_error = Some(format!("DNSSEC check failed: {e}")),
+            }
         }
     }
 
-    let host_url = format!("{}://{}", target_url.scheme(), host);
-    if unique_target.insert(&host_url) {
-        probes.push((host_url.to_string(), "host".to_string()));
+    let rungs = match dns {
+        Some(_) => input.probes.as_deref().unwrap_or(&DEFAULT_LADDER),
+        None => &[],
+    };
+    let probes = ladder(&target_url, rungs);
+
+    let request = ProbeRequest::new(input).map_err(|e| TargetError::new(e, 400))?;
+
+    let settings = ProbeSettings {
+        target_url: target_url.clone(),
+        method: request.method.into(),
+        headers: request.headers().map_err(|e| TargetError::new(e, 400))?,
+        body: input.body.clone(),
+        max_redirects: redirect::max_redirects(input),
+        timeout_ms: input
+            .timeout_ms
+            .unwrap_or(config.probe_timeout_ms)
+            .min(config.max_probe_timeout_ms),
+        max_body_bytes: config.max_body_bytes,
+        body_assertions: BodyAssertions::new(input).map_err(|e| TargetError::new(e, 400))?,
+        retry: RetryPolicy::new(input, config.max_probe_timeout_ms),
+    };
+
+    let propagation = async {
+        let record_type = input.propagation?;
+        let resolvers = propagation::resolvers(
+            input.propagation_resolvers.as_deref(),
+            &config.doh_resolvers,
+            config.doh_method,
+        );
+        Some(propagation::check(host, record_type, &resolvers).await)
+    };
+
+    let record_probes = futures::future::join_all(
+        input
+            .dns_records
+            .iter()
+            .flatten()
+            .map(|assertion| records::probe(assertion, host, &config.doh_resolvers)),
+    );
+
+    let email = async {
+        if input.email_audit != Some(true) {
+            return None;
+        }
+
+        let selectors = input.dkim_selectors.as_deref();
+        Some(email::audit(host, selectors, &config.doh_resolvers).await)
+    };
+
+    let ladder = run_ladder(&probes, input, &settings);
+    let (propagation, email, mut results, record_results) =
+        futures::join!(propagation, email, ladder, record_probes);
+    results.extend(record_results);
+
+    let response = FinalResponse {
+        requested_url: target_url.to_string(),
+        request,
+        dns,
+        propagation,
+        email,
+        results,
+    };
+
+    if response.is_cacheable() {
+        let headers = Headers::new();
+        headers.set("Cache-Control", &response.cache_control(config))?;
+
+        let cache_response = Response::builder()
+            .with_headers(headers)
+            .from_json(&response)?;
+
+        ctx.wait_until(async move {
+            let _ = cache.put(cache_key, cache_response).await;
+        });
     }
 
-    if let Some(domain) = psl::domain_str(host) {
-        let domain_url = format!("{}://{}", target_url.scheme(), domain);
-        if unique_target.insert(&domain_url) {
-            probes.push((domain_url.to_string(), "domain".to_string()));
+    Ok((response, "MISS"))
+}
+
+/// Cache entries are keyed by the target URL and every option that changes its result.
+fn cache_key(target_url: &Url, input: &InputUrl) -> Result<String> {
+    let mut key = Url::parse("https://up-down-workers.cache/")?;
+    key.query_pairs_mut()
+        .append_pair("url", target_url.as_str())
+        .append_pair("options", &serde_json::to_string(input)?);
+
+    Ok(key.to_string())
+}
+
+/// The URL and name of each rung of `target_url` to probe, in order. Rungs that do not apply or
+/// resolve to an already probed URL are skipped.
+fn ladder(target_url: &Url, rungs: &[Rung]) -> Vec<(String, String)> {
+    let mut unique_target = HashSet::new();
+    let mut probes = Vec::new();
+
+    for rung in rungs {
+        let Some(url) = rung.url(target_url) else {
+            continue;
+        };
+
+        let normalized = Url::parse(&url).map_or_else(|_| url.clone(), |u| u.to_string());
+        if unique_target.insert(normalized) {
+            probes.push((url, rung.name().to_string()));
         }
     }
 
+    probes
+}
+
+/// Probe every rung concurrently. In `first-up` mode, the outstanding probes are dropped, aborting
+/// their fetches, as soon as a rung is up and every rung before it is done.
+///
+/// Requests that are not `GET` or `HEAD` may have side effects, so their rungs are probed one
+/// after the other instead, and in `first-up` mode only until a rung is up.
+async fn run_ladder(
+    probes: &[(String, String)],
+    input: &InputUrl,
+    settings: &ProbeSettings,
+) -> Vec<ProbeResult> {
+    if !matches!(settings.method, Method::Get | Method::Head) {
+        let mut results = Vec::new();
+        for (url, probe_type) in probes {
+            let result = probe_with_retries(url, probe_type, input, settings).await;
+            let isup = result.status.is_up();
+            results.push(result);
+            if isup && input.mode == LadderMode::FirstUp {
+                break;
+            }
+        }
+
+        return results;
+    }
+
+    let mut pending = probes
+        .iter()
+        .enumerate()
+        .map(|(index, (url, probe_type))| async move {
+            (
+                index,
+                probe_with_retries(url, probe_type, input, settings).await,
+            )
+        })
+        .collect::<FuturesUnordered<_>>();
+
+    let mut slots = vec![None; probes.len()];
+    while let Some((index, result)) = pending.next().await {
+        slots[index] = Some(result);
+        if input.mode == LadderMode::FirstUp && ladder_decided(&slots) {
+            break;
+        }
+    }
+    drop(pending);
+
+    ladder_results(slots, input.mode)
+}
+
+/// The results of a ladder in rung order, up to the first rung still pending, and in `first-up`
+/// mode up to the first rung that is up.
+fn ladder_results(slots: Vec<Option<ProbeResult>>, mode: LadderMode) -> Vec<ProbeResult> {
     let mut results = Vec::new();
-    for (url, probe_type) in probes {
-        let result = probe(&url, &probe_type).await;
-        let isup = result.status == "UP";
+    for result in slots.into_iter().map_while(|slot| slot) {
+        let isup = result.status.is_up();
         results.push(result);
-        if isup {
+        if isup && mode == LadderMode::FirstUp {
             break;
         }
     }
 
-    let response = FinalResponse {
-        requested_url: target_url.to_string(),
-        results,
-    };
+    results
+}
 
-    let cache_ttl: u32 = env
-        .var("CACHE_TTL_SECONDS")
-        .ok()
-        .and_then(|s| s.to_string().parse().ok())
-        .unwrap_or(600);
+/// Whether a `first-up` ladder is decided: some rung is up and every rung before it is done.
+fn ladder_decided(slots: &[Option<ProbeResult>]) -> bool {
+    for slot in slots {
+        match slot {
+            None => return false,
+            Some(result) if result.status.is_up() => return true,
+            Some(_) => {}
+        }
+    }
 
-    let headers = Headers::new();
-    headers.set("Cache-Control", &format!("max-age={cache_ttl}"))?;
-    headers.set("X-Worker-Cache", "MISS")?;
+    false
+}
 
-    let mut response = Response::builder()
-        .with_headers(headers)
-        .from_json(&response)?;
+/// Worst-case number of subrequests needed to check `input`: the DNS queries to every resolver
+/// and the DNSSEC check, every probe, including the redirects it follows hop by hop and its
+/// retries, the DNS record probes, the propagation check and the email audit.
+fn subrequest_cost(input: &InputUrl, config: &Config) -> usize {
+    let rungs = input.probes.as_ref().map_or(DEFAULT_LADDER.len(), Vec::len);
+    let requests_per_attempt = 1 + redirect::max_redirects(input).unwrap_or(0);
+    let attempts = 1 + RetryPolicy::new(input, config.max_probe_timeout_ms).retries as usize;
 
-    let cache_response = response.cloned()?;
-    ctx.wait_until(async move {
-        let _ = cache.put(cache_key, cache_response).await;
+    let records = input.dns_records.as_ref().map_or(0, Vec::len)
+        * (config.doh_resolvers.len() + dnssec::SUBREQUESTS);
+    let email = match input.email_audit {
+        Some(true) => email::queries(input.dkim_selectors.as_deref()) * config.doh_resolvers.len(),
+        _ => 0,
+    };
+    let propagation = input.propagation.map_or(0, |_| {
+        propagation::resolvers(
+            input.propagation_resolvers.as_deref(),
+            &config.doh_resolvers,
+            config.doh_method,
+        )
+        .len()
     });
 
-    Ok(response)
+    dns::SUBREQUESTS * config.doh_resolvers.len()
+        + dnssec::SUBREQUESTS
+        + rungs * requests_per_attempt * attempts
+        + records
+        + propagation
+        + email
+}
+
+/// Deserialize a list from either a sequence or a single item.
+fn one_or_many<'de, D, T>(deserializer: D) -> std::result::Result<Option<Vec<T>>, D::Error>
+where
+    D: Deserializer<'de>,
+    T: Deserialize<'de>,
+{
+    #[derive(Deserialize)]
+    #[serde(untagged)]
+    enum List<T> {
+        Seq(Vec<T>),
+        One(T),
+    }
+
+    Ok(Some(match List::deserialize(deserializer)? {
+        List::Seq(items) => items,
+        List::One(item) => vec![item],
+    }))
 }
 
-async fn probe(url: &str, probe_type: &str) -> ProbeResult {
-    let headers = Headers::new();
-    headers.set("User-Agent", "up-down-workers/1.0").unwrap();
+/// Deserialize a list from either a sequence, a single item or a comma-separated string, as sent
+/// in GET queries.
+fn comma_separated<'de, D, T>(deserializer: D) -> std::result::Result<Option<Vec<T>>, D::Error>
+where
+    D: Deserializer<'de>,
+    T: DeserializeOwned,
+{
+    #[derive(Deserialize)]
+    #[serde(untagged)]
+    enum List<T> {
+        Str(String),
+        Seq(Vec<T>),
+        One(T),
+    }
+
+    match List::<T>::deserialize(deserializer)? {
+        List::Seq(items) => Ok(Some(items)),
+        List::One(item) => Ok(Some(vec![item])),
+        List::Str(items) => items
+            .split(',')
+            .map(|item| {
+                T::deserialize(item.trim().into_deserializer())
+                    .map_err(|e: serde::de::value::Error| serde::de::Error::custom(e))
+            })
+            .collect::<std::result::Result<_, _>>()
+            .map(Some),
+    }
+}
 
-    let request = Request::new_with_init(
-        url,
-        &RequestInit {
-            method: Method::Get,
-            headers,
-            ..RequestInit::default()
-        },
-    )
-    .unwrap();
+fn env_number<T: std::str::FromStr>(env: &Env, name: &str) -> Option<T> {
+    env.var(name).ok().and_then(|s| s.to_string().parse().ok())
+}
+
+/// Probe `url`, retrying failures covered by the retry policy with an exponential backoff.
+async fn probe_with_retries(
+    url: &str,
+    probe_type: &str,
+    input: &InputUrl,
+    settings: &ProbeSettings,
+) -> ProbeResult {
+    let mut attempts = Vec::new();
+
+    for attempt in 1.. {
+        let result = probe(url, probe_type, input, settings).await;
+        if !settings.retry.should_retry(attempt, &result) {
+            if attempts.is_empty() {
+                return result;
+            }
+
+            attempts.push(Attempt::from(&result));
+            return ProbeResult { attempts, ..result };
+        }
+
+        attempts.push(Attempt::from(&result));
+        Delay::from(settings.retry.backoff(attempt)).await;
+    }
 
+    unreachable!()
+}
+
+async fn probe(
+    url: &str,
+    probe_type: &str,
+    input: &InputUrl,
+    settings: &ProbeSettings,
+) -> ProbeResult {
     let controller = AbortController::default();
     let signal = &controller.signal();
+    let controller = AbortOnDrop(Some(controller));
+
+    let start = Date::now().as_millis();
+    let ttfb_ms = Cell::new(None);
+    let redirects = RefCell::new(Vec::new());
 
     let fetch_fut = async {
-        let result = match Fetch::Request(request).send_with_signal(signal).await {
-            Ok(response) => {
+        let result = match send(url, settings, signal, &redirects).await {
+            Ok((mut response, final_url)) => {
+                ttfb_ms.set(Some(Date::now().as_millis() - start));
+
+                // Read the body until it ends or `max_body_bytes` of it are kept for the
+                // assertions. Dropping the stream then cancels the rest of the download, so a
+                // streaming or huge body neither times out nor counts in the response time.
+                let mut body = Vec::new();
+                if let Ok(mut stream) = response.stream() {
+                    while body.len() < settings.max_body_bytes {
+                        let Some(Ok(chunk)) = stream.next().await else {
+                            break;
+                        };
+                        let room = settings.max_body_bytes - body.len();
+                        body.extend_from_slice(&chunk[..chunk.len().min(room)]);
+                    }
+                }
+                let body = String::from_utf8_lossy(&body);
+
                 let status_code = response.status_code();
-                let status = if (200..400).contains(&status_code) {
-                    "UP"
+                let matched_status = input
+                    .expected_status
+                    .as_deref()
+                    .unwrap_or(&DEFAULT_EXPECTED_STATUS)
+                    .iter()
+                    .find(|rule| rule.matches(status_code));
+                let mut status_text = String::new();
+                let mut failure_reason = None;
+                let mut status = if matched_status.is_some() {
+                    Status::Up
+                } else if status_code == 429 {
+                    status_text = "Rate limited by origin.".to_string();
+                    Status::Degraded
                 } else {
-                    "DOWN"
+                    failure_reason = Some(FailureReason::from_status(status_code));
+                    Status::Down
                 };
 
+                let block = blocking::detect(status_code, response.headers(), &body);
+                if let Some(block) = &block {
+                    status = Status::Blocked;
+                    status_text = format!("Blocked by {}: {}.", block.vendor, block.kind);
+                    failure_reason = None;
+                }
+
+                let cloudflare = cloudflare::detect(status_code, response.headers(), &body);
+                if let (Some(error), Status::Down) = (&cloudflare, status) {
+                    status_text = format!(
+                        "Cloudflare {}: {}. {}",
+                        error.code, error.title, error.explanation
+                    );
+                    if error.is_dns_error() {
+                        failure_reason = Some(FailureReason::DnsNxdomain);
+                    }
+                }
+
+                if status == Status::Up {
+                    if let Err(failure) = redirect::check_final(input, &final_url) {
+                        status = Status::Down;
+                        status_text = failure;
+                        failure_reason = Some(FailureReason::RedirectAssertion);
+                    }
+                }
+
+                let mut failed_field = None;
+                if status == Status::Up {
+                    if let Err(failure) = settings.body_assertions.check(&body) {
+                        status = Status::Down;
+                        status_text = failure.message;
+                        failure_reason = Some(failure.reason);
+                        failed_field = failure.field;
+                    }
+                }
+
+                let mut placeholder = None;
+                if status == Status::Up {
+                    if let Some(page) = placeholder::detect(response.headers(), &body) {
+                        status = page.status;
+                        status_text = format!("Not a real site: {}.", page.kind);
+                        placeholder = Some(page.kind.to_string());
+                    }
+                }
+
+                let content_type = response.headers().get("content-type").ok().flatten();
+                let mut checks = Vec::new();
+                if content_type.is_some_and(|c| c.starts_with(health::HEALTH_JSON)) {
+                    if let Some(report) = health::parse(&body) {
+                        if let (Some(health_status), Status::Up) = (report.status, status) {
+                            status = health_status;
+                            if health_status != Status::Up {
+                                status_text = report.output.unwrap_or_default();
+                            }
+                            if health_status == Status::Down {
+                                failure_reason = Some(FailureReason::HealthCheckFailed);
+                            }
+                        }
+                        checks = report.checks;
+                    }
+                }
+
                 ProbeResult {
                     probe_type: probe_type.to_string(),
                     url: url.to_string(),
-                    status: status.to_string(),
+                    status,
                     status_code: Some(status_code),
-                    status_text: String::new(),
+                    status_text,
+                    failure_reason,
+                    matched_status: matched_status.map(ToString::to_string),
+                    failed_field,
+                    blocked_by: block.map(|block| block.vendor.to_string()),
+                    placeholder,
+                    cloudflare,
+                    checks,
+                    final_url: settings.max_redirects.map(|_| final_url),
+                    ..Default::default()
                 }
             }
-            Err(e) => ProbeResult {
+            Err((failure_reason, status_text)) => ProbeResult {
                 probe_type: probe_type.to_string(),
                 url: url.to_string(),
-                status: "DOWN".to_string(),
+                status: Status::Down,
                 status_code: None,
-                status_text: format!("Fetch to origin error: {e}"),
+                status_text,
+                failure_reason: Some(failure_reason),
+                ..Default::default()
             },
         };
 
         result
     };
 
-    let delay_fut = async {
-        Delay::from(Duration::from_secs(60)).await;
-        controller.abort();
-    };
+    let delay_fut = Delay::from(Duration::from_millis(settings.timeout_ms));
 
     pin_mut!(fetch_fut);
     pin_mut!(delay_fut);
-    match futures::future::select(fetch_fut, delay_fut).await {
+    let mut result = match futures::future::select(fetch_fut, delay_fut).await {
         Either::Left((value, _)) => value,
         Either::Right(_) => ProbeResult {
             probe_type: probe_type.to_string(),
             url: url.to_string(),
-            status: "DOWN".to_string(),
+            status: Status::Down,
             status_code: None,
-            status_text: "Request to origin timed-out after 60 secs.".to_string(),
+            status_text: format!(
+                "Request to origin timed-out after {} ms.",
+                settings.timeout_ms
+            ),
+            failure_reason: Some(FailureReason::Timeout),
+            ..Default::default()
         },
+    };
+
+    drop(controller);
+
+    result.response_time_ms = Date::now().as_millis() - start;
+    result.ttfb_ms = ttfb_ms.get();
+    result.timeout_ms = settings.timeout_ms;
+    result.redirects = redirects.take();
+
+    // Only an otherwise healthy probe is degraded by its latency.
+    if result.status == Status::Up {
+        let slow = [
+            (
+                "Response time",
+                Some(result.response_time_ms),
+                input.degraded_response_time_ms,
+            ),
+            ("Time to first byte", result.ttfb_ms, input.degraded_ttfb_ms),
+        ]
+        .into_iter()
+        .find_map(|(name, value, threshold)| {
+            let (value, threshold) = (value?, threshold?);
+            (value > threshold).then(|| format!("{name} of {value} ms is above {threshold} ms."))
+        });
+
+        if let Some(slow) = slow {
+            result.status = Status::Degraded;
+            result.status_text = slow;
+        }
+    }
+
+    result
+}
+
+/// Aborts the fetch of a probe when dropped, either once it timed out or because the probe was
+/// cancelled. Aborting a finished fetch does nothing.
+struct AbortOnDrop(Option<AbortController>);
+
+impl Drop for AbortOnDrop {
+    fn drop(&mut self) {
+        if let Some(controller) = self.0.take() {
+            controller.abort();
+        }
     }
 }
 
-async fn check_domain(domain: &str) -> Result<u64> {
-    let mut url = Url::parse("https://cloudflare-dns.com/dns-query").unwrap();
-    url.set_query(Some(&format!("name={domain}")));
-    let headers = Headers::new();
-    headers.set("Accept", "application/dns-json").unwrap();
-    let request = Request::new_with_init(
-        url.as_str(),
-        &RequestInit {
-            headers,
-            method: Method::Get,
+/// Send the probe request to `url`, returning the final response and its URL. When
+/// `max_redirects` is set, redirects are followed hop by hop and recorded in `redirects`. Sensitive
+/// headers are only sent over HTTPS to the origin of the requested URL.
+async fn send(
+    url: &str,
+    settings: &ProbeSettings,
+    signal: &AbortSignal,
+    redirects: &RefCell<Vec<RedirectHop>>,
+) -> std::result::Result<(Response, String), (FailureReason, String)> {
+    let mut url = redirect::normalize(url);
+    let mut method = settings.method.clone();
+    let mut body = settings.body.as_deref();
+    let headers = settings.headers.clone();
+    let mut visited = HashSet::from([url.clone()]);
+
+    loop {
+        // Once dropped for a rung or hop, sensitive headers stay dropped for the following hops.
+        if !request::sends_credentials(&url, &settings.target_url) {
+            request::strip_sensitive(&headers);
+        }
+
+        let request = Request::new_with_init(
+            &url,
+            &RequestInit {
+                method: method.clone(),
+                headers: headers.clone(),
+                body: body.map(wasm_bindgen::JsValue::from_str),
+                redirect: match settings.max_redirects {
+                    Some(_) => RequestRedirect::Manual,
+                    None => RequestRedirect::Follow,
+                },
+                ..RequestInit::default()
+            },
+        )
+        .unwrap();
+
+        let response = Fetch::Request(request)
+            .send_with_signal(signal)
+            .await
+            .map_err(|e| {
+                let message = e.to_string();
+                (
+                    FailureReason::from_fetch_error(&message),
+                    format!("Fetch to origin error: {message}"),
+                )
+            })?;
+
+        let Some(max_redirects) = settings.max_redirects else {
+            return Ok((response, url));
+        };
+
+        let Some(location) = redirect::location(&url, &response) else {
+            return Ok((response, url));
+        };
+
+        let status_code = response.status_code();
+        let mut redirects = redirects.borrow_mut();
+        redirects.push(RedirectHop {
+            url: url.clone(),
+            status_code,
+            location: location.clone(),
+        });
+
+        if redirects.len() > max_redirects {
+            return Err((
+                FailureReason::TooManyRedirects,
+                format!("Too many redirects, stopped after {max_redirects}."),
+            ));
+        }
+
+        if !visited.insert(location.clone()) {
+            return Err((
+                FailureReason::RedirectLoop,
+                format!("Redirect loop detected at {location}."),
+            ));
+        }
+
+        // Same as browsers, 303 and POST 301/302 redirects switch to a GET without body.
+        let to_get = match status_code {
+            303 => !matches!(method, Method::Head),
+            301 | 302 => matches!(method, Method::Post),
+            _ => false,
+        };
+
+        if to_get {
+            method = Method::Get;
+            body = None;
+        }
+
+        url = location;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use serde_json::json;
+
+    use super::*;
+
+    fn rung_url(rung: Rung, url: &str) -> Option<String> {
+        rung.url(&Url::parse(url).unwrap())
+    }
+
+    #[test]
+    fn exact_rung_keeps_port_path_and_query() {
+        assert_eq!(
+            rung_url(
+                Rung::Exact,
+                "https://api.example.com:8443/healthz?full=1#top"
+            ),
+            Some("https://api.example.com:8443/healthz?full=1".to_string())
+        );
+        assert_eq!(
+            rung_url(Rung::Exact, "https://example.com:8443"),
+            Some("https://example.com:8443/".to_string())
+        );
+        assert_eq!(
+            rung_url(Rung::Exact, "http://example.com/?q"),
+            Some("http://example.com/?q".to_string())
+        );
+    }
+
+    #[test]
+    fn exact_rung_is_skipped_for_the_host_root() {
+        assert_eq!(rung_url(Rung::Exact, "https://example.com"), None);
+        assert_eq!(rung_url(Rung::Exact, "https://example.com/"), None);
+        assert_eq!(rung_url(Rung::Exact, "https://example.com/#top"), None);
+        assert_eq!(rung_url(Rung::Exact, "https://[2001:db8::1]/"), None);
+    }
+
+    #[test]
+    fn host_rung_is_the_root_of_the_host() {
+        assert_eq!(
+            rung_url(Rung::Host, "https://api.example.com:8443/healthz?full=1"),
+            Some("https://api.example.com".to_string())
+        );
+        assert_eq!(
+            rung_url(Rung::Host, "http://192.0.2.1/status"),
+            Some("http://192.0.2.1".to_string())
+        );
+        assert_eq!(
+            rung_url(Rung::Host, "https://[2001:db8::1]:8443/"),
+            Some("https://[2001:db8::1]".to_string())
+        );
+    }
+
+    #[test]
+    fn www_and_domain_rungs_use_the_registrable_domain() {
+        let url = "https://api.eu.example.co.uk:8443/healthz";
+        assert_eq!(
+            rung_url(Rung::Www, url),
+            Some("https://www.example.co.uk".to_string())
+        );
+        assert_eq!(
+            rung_url(Rung::Domain, url),
+            Some("https://example.co.uk".to_string())
+        );
+        assert_eq!(
+            rung_url(Rung::Www, "http://example.com"),
+            Some("http://www.example.com".to_string())
+        );
+    }
+
+    #[test]
+    fn http_scheme_rung_is_the_host_root_over_http() {
+        assert_eq!(
+            rung_url(Rung::HttpScheme, "https://www.example.com:8443/path"),
+            Some("http://www.example.com".to_string())
+        );
+        assert_eq!(
+            rung_url(Rung::HttpScheme, "https://[2001:db8::1]/"),
+            Some("http://[2001:db8::1]".to_string())
+        );
+    }
+
+    #[test]
+    fn ip_hosts_have_no_domain_rungs() {
+        for url in ["https://192.0.2.1/status", "https://[2001:db8::1]:8443/"] {
+            assert_eq!(rung_url(Rung::Www, url), None, "{url}");
+            assert_eq!(rung_url(Rung::Domain, url), None, "{url}");
+        }
+    }
+
+    #[test]
+    fn ladder_skips_duplicate_and_missing_rungs() {
+        let all = [
+            Rung::Exact,
+            Rung::Host,
+            Rung::Www,
+            Rung::Domain,
+            Rung::HttpScheme,
+        ];
+        let names = |url: &str| -> Vec<String> {
+            ladder(&Url::parse(url).unwrap(), &all)
+                .into_iter()
+                .map(|(_, name)| name)
+                .collect()
+        };
+
+        assert_eq!(
+            names("https://example.com/"),
+            ["host", "www", "http-scheme"]
+        );
+        assert_eq!(
+            names("https://www.example.com/path"),
+            ["exact", "host", "domain", "http-scheme"]
+        );
+        assert_eq!(names("http://example.com"), ["host", "www"]);
+        assert_eq!(
+            names("https://192.0.2.1/status"),
+            ["exact", "host", "http-scheme"]
+        );
+
+        let probes = ladder(
+            &Url::parse("https://api.example.com/healthz").unwrap(),
+            &DEFAULT_LADDER,
+        );
+        let urls: Vec<&str> = probes.iter().map(|(url, _)| url.as_str()).collect();
+        assert_eq!(
+            urls,
+            [
+                "https://api.example.com/healthz",
+                "https://api.example.com",
+                "https://example.com",
+            ]
+        );
+    }
+
+    #[test]
+    fn parses_batch_items_one_by_one() {
+        let body = json!([
+            {"url": "example.com"},
+            {"url": "example.org", "timeout_ms": "x"},
+            {"expected_status": "2xx"},
+        ]);
+        let Ok(Input::Batch(items)) = Input::from_json(body) else {
+            panic!("not a batch");
+        };
+
+        let items: Vec<_> = items.into_iter().map(batch_input).collect();
+        assert!(matches!(&items[0], Ok(input) if input.url == "example.com"));
+        assert!(matches!(
+            &items[1],
+            Err(BatchItem::Err { requested_url, error, status_code: 400 })
+                if requested_url == "example.org" && error.starts_with("invalid type: string \"x\"")
+        ));
+        assert!(matches!(
+            &items[2],
+            Err(BatchItem::Err { requested_url, error, status_code: 400 })
+                if requested_url.is_empty() && error.contains("url")
+        ));
+    }
+
+    #[test]
+    fn reports_invalid_fields_of_single_targets() {
+        let Err(e) = Input::from_json(json!({"url": "example.com", "mode": "some"})) else {
+            panic!("parsed an invalid mode");
+        };
+        assert!(e.to_string().contains("some"), "{e}");
+    }
+
+    fn result(probe_type: &str, status: Status) -> ProbeResult {
+        ProbeResult {
+            probe_type: probe_type.to_string(),
+            status,
             ..Default::default()
-        },
-    )?;
-    let mut response = Fetch::Request(request).send().await?;
-    let obj = response.json::<serde_json::Value>().await?;
+        }
+    }
+
+    #[test]
+    fn caches_targets_up_on_their_ladder_only() {
+        let mut response: FinalResponse = serde_json::from_value(json!({
+            "requested_url": "https://example.com/",
+            "request": {"method": "GET", "headers": {}, "body_bytes": null},
+            "results": [],
+        }))
+        .unwrap();
+        assert!(!response.is_cacheable());
+
+        // Records that resolve, for a host whose ladder is down or was skipped.
+        response.results = vec![result(records::PROBE_TYPE, Status::Up)];
+        assert!(!response.is_cacheable());
+        response.results.push(result("host", Status::Down));
+        assert!(!response.is_cacheable());
+
+        response.results.push(result("domain", Status::Degraded));
+        assert!(response.is_cacheable());
+    }
+
+    fn types(results: &[ProbeResult]) -> Vec<&str> {
+        results.iter().map(|r| r.probe_type.as_str()).collect()
+    }
+
+    #[test]
+    fn decides_first_up_ladders() {
+        let (down, up) = (Status::Down, Status::Up);
 
-    let obj = obj.as_object().unwrap();
-    let status = obj.get("Status").unwrap().as_u64().unwrap();
+        // An earlier rung is still pending, it may be up too.
+        let slots = [None, Some(result("host", up))];
+        assert!(!ladder_decided(&slots));
 
-    console_log!("check_domain {domain}: {status}");
+        let slots = [Some(result("exact", down)), Some(result("host", up)), None];
+        assert!(ladder_decided(&slots));
 
-    Ok(status)
+        let slots = [Some(result("exact", down)), Some(result("host", down))];
+        assert!(!ladder_decided(&slots));
+
+        assert!(!ladder_decided(&[]));
+    }
+
+    #[test]
+    fn keeps_ladder_results_up_to_the_first_pending_or_up_rung() {
+        let (down, up) = (Status::Down, Status::Up);
+
+        let slots = vec![None, Some(result("host", up))];
+        assert!(ladder_results(slots, LadderMode::FirstUp).is_empty());
+
+        let slots = vec![
+            Some(result("exact", down)),
+            Some(result("host", up)),
+            Some(result("domain", up)),
+        ];
+        let results = ladder_results(slots.clone(), LadderMode::FirstUp);
+        assert_eq!(types(&results), ["exact", "host"]);
+        let results = ladder_results(slots, LadderMode::All);
+        assert_eq!(types(&results), ["exact", "host", "domain"]);
+
+        let slots = vec![
+            Some(result("exact", down)),
+            Some(result("host", down)),
+            Some(result("domain", down)),
+        ];
+        let results = ladder_results(slots, LadderMode::FirstUp);
+        assert_eq!(types(&results), ["exact", "host", "domain"]);
+    }
 }
@@ -0,0 +1,225 @@
+use std::sync::LazyLock;
+
+use regex::Regex;
+use worker::Headers;
+
+use crate::Status;
+
+/// A parked domain or placeholder page answered instead of a real site.
+pub struct Placeholder {
+    /// Either `Status::Parked` or `Status::Placeholder`.
+    pub status: Status,
+    pub kind: &'static str,
+}
+
+/// Pages up to this size are placeholders as a whole, so their content is searched for snippets.
+/// Larger pages are real sites that may well mention them.
+const SMALL_PAGE_BYTES: usize = 8 * 1024;
+
+/// A parking service or domain marketplace script, frame or redirect, which is how parking pages
+/// are served. A plain link to them is not enough, real sites link to marketplaces too.
+static PARKING_SERVICE: LazyLock<Regex> = LazyLock::new(|| {
+    Regex::new(concat!(
+        r#"(?i)(?:<(?:script|iframe|frame)\b[^>]*\bsrc\s*=|\burl\s*=|location(?:\.href)?\s*=)"#,
+        r#"\s*["']?(?:https?:)?//(?:[\w-]+\.)*"#,
+        r#"(?:sedoparking\.com|parkingcrew\.net|bodis\.com|parklogic\.com|above\.com|"#,
+        r#"afternic\.com|hugedomains\.com|dan\.com)\b"#,
+    ))
+    .unwrap()
+});
+
+/// Phrases that mark a parked domain in the page title.
+const PARKED_TITLES: &[&str] = &[
+    "domain is parked",
+    "parked domain",
+    "domain is for sale",
+    "domain may be for sale",
+    "buy this domain",
+];
+
+/// Snippets of parking pages, only searched in small pages.
+const PARKED_SNIPPETS: &[&str] = &[
+    "this domain is parked",
+    "parked free, courtesy of",
+    "domain is for sale",
+    "domain may be for sale",
+    "buy this domain",
+];
+
+/// Phrases that mark a default web server page, a suspended hosting account or an unfinished site
+/// in the page title.
+const PLACEHOLDER_TITLES: &[(&str, &str)] = &[
+    ("welcome to nginx!", "default nginx page"),
+    ("apache2 ubuntu default page", "default Apache page"),
+    ("apache2 debian default page", "default Apache page"),
+    (
+        "test page for the apache http server",
+        "default Apache page",
+    ),
+    ("iis windows server", "default IIS page"),
+    ("caddy works!", "default Caddy page"),
+    ("litespeed web server", "default LiteSpeed page"),
+    ("account suspended", "suspended account"),
+    ("website is suspended", "suspended account"),
+    ("coming soon", "coming soon page"),
+    ("under construction", "under construction page"),
+    ("launching soon", "coming soon page"),
+];
+
+/// Snippets of default web server pages and suspended hosting accounts, only searched in small
+/// pages.
+const PLACEHOLDER_SNIPPETS: &[(&str, &str)] = &[
+    ("<h1>it works!</h1>", "default Apache page"),
+    ("account has been suspended", "suspended account"),
+    ("suspendedpage.cgi", "suspended account"),
+];
+
+/// Detect a parked domain or a placeholder page from the headers and body of a response.
+pub fn detect(headers: &Headers, body: &str) -> Option<Placeholder> {
+    let parking_server = headers
+        .get("server")
+        .ok()
+        .flatten()
+        .is_some_and(|server| server.to_ascii_lowercase().contains("parking"));
+
+    if parking_server {
+        return Some(Placeholder {
+            status: Status::Parked,
+            kind: "parked domain",
+        });
+    }
+
+    detect_page(body)
+}
+
+/// Detect a parked domain or a placeholder page from its title, its markup or, for small pages,
+/// its content.
+fn detect_page(body: &str) -> Option<Placeholder> {
+    let body = body.to_ascii_lowercase();
+    let title = body
+        .split_once("<title")
+        .and_then(|(_, rest)| rest.split_once('>'))
+        .and_then(|(_, rest)| rest.split_once("</title>"))
+        .map_or("", |(title, _)| title.trim());
+    let small = body.len() <= SMALL_PAGE_BYTES;
+
+    let parked = PARKING_SERVICE.is_match(&body)
+        || PARKED_TITLES.iter().any(|phrase| title.contains(phrase))
+        || (small && PARKED_SNIPPETS.iter().any(|snippet| body.contains(snippet)));
+
+    if parked {
+        return Some(Placeholder {
+            status: Status::Parked,
+            kind: "parked domain",
+        });
+    }
+
+    PLACEHOLDER_TITLES
+        .iter()
+        .find(|(phrase, _)| title.contains(phrase))
+        .or_else(|| {
+            PLACEHOLDER_SNIPPETS
+                .iter()
+                .find(|(snippet, _)| small && body.contains(snippet))
+        })
+        .map(|&(_, kind)| Placeholder {
+            status: Status::Placeholder,
+            kind,
+        })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn kind(body: &str) -> Option<&'static str> {
+        detect_page(body).map(|page| page.kind)
+    }
+
+    /// A real page, larger than `SMALL_PAGE_BYTES`, with `content` in its body.
+    fn large_page(title: &str, content: &str) -> String {
+        format!(
+            "<html><head><title>{title}</title></head><body>{content}<p>{}</p></body></html>",
+            "Lorem ipsum dolor sit amet. ".repeat(400)
+        )
+    }
+
+    #[test]
+    fn detects_parking_services() {
+        let page = large_page(
+            "example.com",
+            r#"<script src="https://www.sedoparking.com/frmpark/example.com/js"></script>"#,
+        );
+        assert_eq!(kind(&page), Some("parked domain"));
+
+        let page = r#"<meta http-equiv="refresh" content="0; url=https://dan.com/buy-domain/x">"#;
+        assert_eq!(kind(page), Some("parked domain"));
+
+        let page = r#"<script>window.location.href = "//ww1.parkingcrew.net/?d=x";</script>"#;
+        assert_eq!(kind(page), Some("parked domain"));
+    }
+
+    #[test]
+    fn detects_parked_titles_and_small_pages() {
+        let page = large_page("example.com is for sale | Buy this domain", "");
+        assert_eq!(kind(&page), Some("parked domain"));
+
+        let page = "<html><body><h1>This domain is for sale!</h1></body></html>";
+        assert_eq!(kind(page), Some("parked domain"));
+    }
+
+    #[test]
+    fn detects_placeholder_pages() {
+        let page = "<html><head><title>Welcome to nginx!</title></head></html>";
+        assert_eq!(kind(page), Some("default nginx page"));
+
+        assert_eq!(
+            kind("<html><body><h1>It works!</h1></body></html>"),
+            Some("default Apache page")
+        );
+
+        let page = r#"<script>location.href = "/cgi-sys/suspendedPage.cgi";</script>"#;
+        assert_eq!(kind(page), Some("suspended account"));
+
+        let page = large_page("Coming Soon", "");
+        assert_eq!(kind(&page), Some("coming soon page"));
+    }
+
+    #[test]
+    fn ignores_mentions_in_real_pages() {
+        let pages = [
+            large_page(
+                "How to sell your domain",
+                r#"Sell it on <a href="https://dan.com">Dan</a> or HugeDomains.com. This domain is
+                for sale, buy this domain, parked free, courtesy of GoDaddy."#,
+            ),
+            large_page(
+                "Server setup",
+                "You should now see <h1>It works!</h1> or Welcome to nginx! in your browser.",
+            ),
+            large_page(
+                "Hosting FAQ",
+                "Your account has been suspended? Contact support before /suspendedpage.cgi shows.",
+            ),
+            large_page(
+                "Blog",
+                "<p>New features coming soon, our site is under construction.</p>",
+            ),
+            large_page(
+                "Tools",
+                r#"<script src="https://cdn.example.com/sedoparking.com.js"></script>"#,
+            ),
+        ];
+
+        for page in &pages {
+            assert_eq!(kind(page), None);
+        }
+    }
+
+    #[test]
+    fn ignores_small_real_pages() {
+        assert_eq!(kind("<html><title>Home</title><p>Hello</p></html>"), None);
+        assert_eq!(kind(r#"{"status": "ok"}"#), None);
+        assert_eq!(kind(""), None);
+    }
+}
@@ -0,0 +1,109 @@
+use futures::future::join_all;
+use serde::{Deserialize, Serialize};
+
+use crate::dns::{self, DnsRecord, DohMethod, RecordType, Resolver};
+
+/// Public resolvers always queried by a propagation check, on top of `DOH_RESOLVERS`.
+pub const PUBLIC_RESOLVERS: [&str; 3] = [
+    "https://cloudflare-dns.com/dns-query",
+    "https://dns.google/dns-query",
+    "https://dns.quad9.net/dns-query",
+];
+
+/// Whether a set of resolvers agree on the records of a host.
+#[derive(Serialize, Deserialize, Clone)]
+pub struct Propagation {
+    #[serde(rename = "type")]
+    pub record_type: RecordType,
+    /// Every resolver answered with the same response code and the same record values.
+    pub consistent: bool,
+    pub resolvers: Vec<ResolverAnswer>,
+}
+
+/// The answer of a single resolver, or why it did not answer.
+#[derive(Serialize, Deserialize, Clone)]
+pub struct ResolverAnswer {
+    pub resolver: String,
+    pub rcode: Option<String>,
+    /// See [`dns::Answer::authenticated`].
+    #[serde(default)]
+    pub authenticated: bool,
+    pub records: Vec<DnsRecord>,
+    pub error: Option<String>,
+}
+
+/// Resolvers of a propagation check: the requested ones, or the public resolvers followed by the
+/// configured ones. Requested and public resolvers are queried with `method`.
+pub fn resolvers(
+    requested: Option<&[String]>,
+    configured: &[Resolver],
+    method: DohMethod,
+) -> Vec<Resolver> {
+    let resolver = |url: &str| Resolver {
+        url: url.to_string(),
+        method,
+    };
+
+    if let Some(requested) = requested {
+        return requested.iter().map(|url| resolver(url)).collect();
+    }
+
+    let mut resolvers = PUBLIC_RESOLVERS.map(resolver).to_vec();
+    for configured in configured {
+        if !resolvers
+            .iter()
+            .any(|resolver| resolver.url == configured.url)
+        {
+            resolvers.push(configured.clone());
+        }
+    }
+
+    resolvers
+}
+
+/// Query every resolver in parallel for the `record_type` records of `host`.
+pub async fn check(host: &str, record_type: RecordType, resolvers: &[Resolver]) -> Propagation {
+    let answers = join_all(resolvers.iter().map(|resolver| async move {
+        match dns::query(host, resolver, record_type).await {
+            Ok(answer) => ResolverAnswer {
+                resolver: resolver.url.clone(),
+                rcode: Some(dns::rcode_name(answer.rcode)),
+                authenticated: answer.authenticated,
+                records: answer.records,
+                error: None,
+            },
+            Err(e) => ResolverAnswer {
+                resolver: resolver.url.clone(),
+                rcode: None,
+                authenticated: false,
+                records: Vec::new(),
+                error: Some(e.to_string()),
+            },
+        }
+    }))
+    .await;
+
+    // `CNAME` records leading to the answer and TTLs are expected to differ between resolvers.
+    let values = |answer: &ResolverAnswer| {
+        let mut values = answer
+            .records
+            .iter()
+            .filter(|record| record.record_type == record_type.name())
+            .map(|record| record.data.to_lowercase())
+            .collect::<Vec<_>>();
+        values.sort();
+        values.dedup();
+        (answer.rcode.clone(), values)
+    };
+
+    let consistent = answers.iter().all(|answer| answer.rcode.is_some())
+        && answers
+            .windows(2)
+            .all(|pair| values(&pair[0]) == values(&pair[1]));
+
+    Propagation {
+        record_type,
+        consistent,
+        resolvers: answers,
+    }
+}
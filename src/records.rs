@@ -0,0 +1,245 @@
+use serde::{Deserialize, Serialize};
+use worker::Date;
+
+use crate::dns::{self, DnsError, DnsRecord, RecordType, Resolver};
+use crate::dnssec;
+use crate::failure::FailureReason;
+use crate::{ProbeResult, Status};
+
+/// A DNS record probe: the records of a name and the values they must have.
+#[derive(Deserialize, Serialize, Clone)]
+#[serde(deny_unknown_fields)]
+pub struct RecordAssertion {
+    #[serde(rename = "type")]
+    record_type: RecordType,
+    /// Name to query, the target host when missing.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    name: Option<String>,
+    /// Values that must be among the records. Any record will do when missing.
+    #[serde(
+        default,
+        deserialize_with = "crate::one_or_many",
+        skip_serializing_if = "Option::is_none"
+    )]
+    expected: Option<Vec<String>>,
+    /// Whether the records must be exactly the expected values, nothing more.
+    #[serde(default, skip_serializing_if = "std::ops::Not::not")]
+    exact: bool,
+}
+
+/// `type` of the results of DNS record probes, next to the ladder results.
+pub const PROBE_TYPE: &str = "dns";
+
+/// Probe the records of `assertion`, `UP` when they exist and have the expected values.
+pub async fn probe(assertion: &RecordAssertion, host: &str, resolvers: &[Resolver]) -> ProbeResult {
+    let name = assertion.name.as_deref().unwrap_or(host);
+    let record_type = assertion.record_type;
+    let start = Date::now().as_millis();
+
+    let mut result = ProbeResult {
+        probe_type: PROBE_TYPE.to_string(),
+        url: format!("{name} {}", record_type.name()),
+        ..Default::default()
+    };
+
+    let answer = match dns::lookup(name, record_type, resolvers).await {
+        Err(DnsError::Rcode(2)) => Err(dnssec::diagnose(name, resolvers.first()).await),
+        answer => answer,
+    };
+
+    match answer {
+        Ok((_, answer)) => {
+            let records = answer
+                .records
+                .into_iter()
+                .filter(|record| record.record_type == record_type.name())
+                .collect::<Vec<_>>();
+
+            match check(assertion, &records) {
+                Ok(()) => result.status = Status::Up,
+                Err((reason, message)) => {
+                    result.failure_reason = Some(reason);
+                    result.status_text = message;
+                }
+            }
+            result.records = records;
+        }
+        Err(e) => {
+            result.failure_reason = Some(match e {
+                DnsError::Rcode(3) => FailureReason::DnsNxdomain,
+                DnsError::Rcode(_) => FailureReason::DnsServfail,
+                DnsError::Dnssec => FailureReason::DnssecFailure,
+                DnsError::Resolver { .. } => FailureReason::FetchError,
+            });
+            result.status_text = match e {
+                DnsError::Rcode(rcode) => {
+                    format!("DNS lookup failed [{}].", dns::rcode_name(rcode))
+                }
+                e => e.to_string(),
+            };
+        }
+    }
+
+    result.response_time_ms = Date::now().as_millis() - start;
+    result
+}
+
+fn check(
+    assertion: &RecordAssertion,
+    records: &[DnsRecord],
+) -> Result<(), (FailureReason, String)> {
+    let record_type = assertion.record_type.name();
+    if records.is_empty() {
+        return Err((
+            FailureReason::DnsNoRecords,
+            format!("No {record_type} record found."),
+        ));
+    }
+
+    let Some(expected) = &assertion.expected else {
+        return Ok(());
+    };
+
+    let actual = records
+        .iter()
+        .map(|record| normalize(assertion.record_type, &record.data))
+        .collect::<Vec<_>>();
+    let expected = expected
+        .iter()
+        .map(|value| normalize(assertion.record_type, value))
+        .collect::<Vec<_>>();
+
+    if let Some(missing) = expected.iter().find(|value| !actual.contains(value)) {
+        return Err((
+            FailureReason::DnsRecordMismatch,
+            format!("No {record_type} record is `{missing}`."),
+        ));
+    }
+
+    if assertion.exact {
+        if let Some(unexpected) = actual.iter().find(|value| !expected.contains(value)) {
+            return Err((
+                FailureReason::DnsRecordMismatch,
+                format!("Unexpected {record_type} record `{unexpected}`."),
+            ));
+        }
+    }
+
+    Ok(())
+}
+
+/// Normalize a record value for comparison: names are case-insensitive and fully qualified names
+/// end with a dot, while a `TXT` value may be split into several quoted and escaped strings, and
+/// the value of a `CAA` record is quoted in answers but not necessarily when expected.
+fn normalize(record_type: RecordType, value: &str) -> String {
+    let value = value.trim();
+
+    if record_type == RecordType::Txt {
+        return dns::txt_text(value);
+    }
+
+    if record_type == RecordType::Caa {
+        let mut parts = value.splitn(3, char::is_whitespace);
+        if let (Some(flags), Some(tag), Some(ca)) = (parts.next(), parts.next(), parts.next()) {
+            let ca = dns::txt_text(ca);
+            return format!("{flags} {tag} {ca}").to_ascii_lowercase();
+        }
+    }
+
+    value
+        .split_whitespace()
+        .map(|part| part.trim_end_matches('.').to_ascii_lowercase())
+        .collect::<Vec<_>>()
+        .join(" ")
+}
+
+#[cfg(test)]
+mod tests {
+    use serde_json::json;
+
+    use super::*;
+
+    fn assertion(record_type: &str, expected: &[&str], exact: bool) -> RecordAssertion {
+        serde_json::from_value(json!({"type": record_type, "expected": expected, "exact": exact}))
+            .unwrap()
+    }
+
+    fn records(record_type: &str, data: &[&str]) -> Vec<DnsRecord> {
+        data.iter()
+            .map(|data| DnsRecord {
+                name: "example.com.".to_string(),
+                record_type: record_type.to_string(),
+                ttl: 300,
+                data: data.to_string(),
+            })
+            .collect()
+    }
+
+    fn reason(result: Result<(), (FailureReason, String)>) -> Option<FailureReason> {
+        result.err().map(|(reason, _)| reason)
+    }
+
+    #[test]
+    fn checks_expected_records_as_a_subset_or_exactly() {
+        let a = records("A", &["93.184.215.14", "93.184.215.15"]);
+
+        assert!(check(&assertion("A", &["93.184.215.14"], false), &a).is_ok());
+        assert!(check(&assertion("A", &[], false), &a).is_ok());
+        assert!(
+            reason(check(&assertion("A", &["93.184.215.14"], true), &a))
+                == Some(FailureReason::DnsRecordMismatch)
+        );
+        assert!(check(
+            &assertion("A", &["93.184.215.15", "93.184.215.14"], true),
+            &a
+        )
+        .is_ok());
+        assert_eq!(
+            check(&assertion("A", &["10.0.0.1"], false), &a)
+                .err()
+                .unwrap()
+                .1,
+            "No A record is `10.0.0.1`."
+        );
+        assert!(
+            reason(check(&assertion("A", &[], false), &[])) == Some(FailureReason::DnsNoRecords)
+        );
+    }
+
+    #[test]
+    fn ignores_the_case_and_trailing_dot_of_names() {
+        let mx = records("MX", &["10 MX1.Example.com.", "20 mx2.example.com."]);
+        let expected = assertion("MX", &["10 mx1.example.com", "20 MX2.EXAMPLE.COM."], true);
+        assert!(check(&expected, &mx).is_ok());
+
+        let cname = records("CNAME", &["Example.Herokudns.com."]);
+        assert!(check(
+            &assertion("CNAME", &["example.herokudns.com"], true),
+            &cname
+        )
+        .is_ok());
+    }
+
+    #[test]
+    fn joins_txt_values_split_into_several_strings() {
+        let txt = records("TXT", &[r#""v=spf1 include:_spf.example.com " "~all""#]);
+        let expected = assertion("TXT", &["v=spf1 include:_spf.example.com ~all"], true);
+        assert!(check(&expected, &txt).is_ok());
+
+        assert_eq!(normalize(RecordType::Txt, r#""say \"hi\"""#), r#"say "hi""#);
+        assert_eq!(normalize(RecordType::Txt, "unquoted"), "unquoted");
+    }
+
+    #[test]
+    fn compares_caa_values_with_or_without_quotes() {
+        let caa = records("CAA", &[r#"0 issue "letsencrypt.org""#]);
+
+        assert!(check(&assertion("CAA", &["0 issue letsencrypt.org"], true), &caa).is_ok());
+        assert!(check(
+            &assertion("CAA", &[r#"0 ISSUE "LetsEncrypt.org""#], true),
+            &caa
+        )
+        .is_ok());
+        assert!(check(&assertion("CAA", &["0 issue pki.goog"], false), &caa).is_err());
+    }
+}
@@ -0,0 +1,138 @@
+use serde::{Deserialize, Serialize};
+use worker::{Response, Url};
+
+use crate::InputUrl;
+
+const DEFAULT_MAX_REDIRECTS: usize = 10;
+
+#[derive(Deserialize, Serialize, Clone, Copy, Default, PartialEq)]
+#[serde(rename_all = "kebab-case")]
+pub enum RedirectMode {
+    /// Let the runtime follow redirects.
+    #[default]
+    Follow,
+    /// Follow redirects hop by hop, recording each of them.
+    Manual,
+}
+
+/// A redirect met while following the redirect chain of a probe.
+#[derive(Serialize, Deserialize, Clone)]
+pub struct RedirectHop {
+    pub url: String,
+    pub status_code: u16,
+    pub location: String,
+}
+
+/// How many redirects a probe of `input` may follow hop by hop, `None` when the runtime follows
+/// them instead. Redirect assertions need the chain, so they imply the manual mode.
+pub fn max_redirects(input: &InputUrl) -> Option<usize> {
+    let manual = input.redirect == Some(RedirectMode::Manual)
+        || input.expected_final_url.is_some()
+        || input.expected_final_host.is_some();
+
+    manual.then(|| input.max_redirects.unwrap_or(DEFAULT_MAX_REDIRECTS))
+}
+
+/// The URL a redirect `response` for `url` points to, `None` if it is not a redirect.
+pub fn location(url: &str, response: &Response) -> Option<String> {
+    if !(300..400).contains(&response.status_code()) {
+        return None;
+    }
+
+    let location = response.headers().get("location").ok().flatten()?;
+    let mut location = Url::parse(url).ok()?.join(&location).ok()?;
+    location.set_fragment(None);
+
+    Some(location.to_string())
+}
+
+/// `url` parsed and without its fragment, as redirect locations are, so that the final URL of a
+/// probe compares the same with or without redirects. Left as is if it does not parse.
+pub fn normalize(url: &str) -> String {
+    Url::parse(url).map_or_else(
+        |_| url.to_string(),
+        |mut url| {
+            url.set_fragment(None);
+            url.to_string()
+        },
+    )
+}
+
+/// Check where the redirect chain ended against the redirect assertions of `input`.
+pub fn check_final(input: &InputUrl, final_url: &str) -> Result<(), String> {
+    if let Some(expected) = &input.expected_final_url {
+        let matches = match (Url::parse(expected), Url::parse(final_url)) {
+            (Ok(mut expected), Ok(final_url)) => {
+                expected.set_fragment(None);
+                expected == final_url
+            }
+            _ => false,
+        };
+
+        if !matches {
+            return Err(format!(
+                "Redirect assertion failed: final URL is {final_url}, expected {expected}."
+            ));
+        }
+    }
+
+    if let Some(expected) = &input.expected_final_host {
+        let host = Url::parse(final_url)
+            .ok()
+            .and_then(|url| url.host_str().map(str::to_string))
+            .unwrap_or_default();
+
+        if !host.eq_ignore_ascii_case(expected) {
+            return Err(format!(
+                "Redirect assertion failed: final host is {host}, expected {expected}."
+            ));
+        }
+    }
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use serde_json::json;
+
+    use super::*;
+
+    fn input_with(assertions: serde_json::Value) -> InputUrl {
+        let mut input = json!({ "url": "https://example.com" });
+        input
+            .as_object_mut()
+            .unwrap()
+            .extend(assertions.as_object().unwrap().clone());
+        serde_json::from_value(input).unwrap()
+    }
+
+    #[test]
+    fn checks_the_final_url() {
+        let input = input_with(json!({ "expected_final_url": "https://www.example.com" }));
+
+        // The URL of a rung answered without redirect.
+        assert!(check_final(&input, &normalize("https://www.example.com")).is_ok());
+        assert!(check_final(&input, &normalize("https://www.example.com/#top")).is_ok());
+        assert!(check_final(&input, "https://www.example.com/login").is_err());
+        assert!(check_final(&input, "http://www.example.com/").is_err());
+
+        let input = input_with(json!({ "expected_final_url": "HTTPS://Example.com:443/a?b#c" }));
+        assert!(check_final(&input, "https://example.com/a?b").is_ok());
+        assert!(check_final(&input, "https://example.com/a").is_err());
+    }
+
+    #[test]
+    fn checks_the_final_host() {
+        let input = input_with(json!({ "expected_final_host": "WWW.example.com" }));
+
+        assert!(check_final(&input, "https://www.example.com/login").is_ok());
+        assert!(check_final(&input, "https://example.com/").is_err());
+        assert!(check_final(&input, "not a url").is_err());
+    }
+
+    #[test]
+    fn passes_without_assertions() {
+        assert!(check_final(&input_with(json!({})), "https://example.com/").is_ok());
+    }
+}
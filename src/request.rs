@@ -0,0 +1,176 @@
+use std::collections::hash_map::DefaultHasher;
+use std::collections::BTreeMap;
+use std::hash::{Hash, Hasher};
+
+use serde::{Deserialize, Serialize, Serializer};
+use worker::{Headers, Method, Url};
+
+use crate::InputUrl;
+
+const DEFAULT_USER_AGENT: &str = "up-down-workers/1.0";
+const REDACTED: &str = "[REDACTED]";
+
+/// HTTP methods a probe may use.
+#[derive(Deserialize, Serialize, Clone, Copy, Default, PartialEq)]
+#[serde(rename_all = "UPPERCASE")]
+pub enum ProbeMethod {
+    #[default]
+    #[serde(alias = "get")]
+    Get,
+    #[serde(alias = "head")]
+    Head,
+    #[serde(alias = "post")]
+    Post,
+    #[serde(alias = "put")]
+    Put,
+    #[serde(alias = "options")]
+    Options,
+}
+
+impl From<ProbeMethod> for Method {
+    fn from(method: ProbeMethod) -> Self {
+        match method {
+            ProbeMethod::Get => Method::Get,
+            ProbeMethod::Head => Method::Head,
+            ProbeMethod::Post => Method::Post,
+            ProbeMethod::Put => Method::Put,
+            ProbeMethod::Options => Method::Options,
+        }
+    }
+}
+
+/// The request sent by every probe of a target, as reported in the response.
+#[derive(Serialize, Deserialize, Clone)]
+pub struct ProbeRequest {
+    pub method: ProbeMethod,
+    /// Lowercase header names and their values, sensitive ones are redacted when serialized.
+    #[serde(serialize_with = "redact_headers")]
+    pub headers: BTreeMap<String, String>,
+    /// Size of the request body, the body itself is never reported.
+    pub body_bytes: Option<usize>,
+}
+
+impl ProbeRequest {
+    /// Validate the request options of `input`.
+    pub fn new(input: &InputUrl) -> Result<Self, String> {
+        let method = input.method.unwrap_or_default();
+        if input.body.is_some() && matches!(method, ProbeMethod::Get | ProbeMethod::Head) {
+            return Err("A request body is only allowed with POST, PUT or OPTIONS.".to_string());
+        }
+
+        let mut headers: BTreeMap<String, String> = input
+            .headers
+            .iter()
+            .flatten()
+            .map(|(name, value)| (name.to_ascii_lowercase(), value.clone()))
+            .collect();
+
+        if let Some(user_agent) = &input.user_agent {
+            headers.insert("user-agent".to_string(), user_agent.clone());
+        }
+
+        headers
+            .entry("user-agent".to_string())
+            .or_insert_with(|| DEFAULT_USER_AGENT.to_string());
+
+        Ok(Self {
+            method,
+            headers,
+            body_bytes: input.body.as_ref().map(String::len),
+        })
+    }
+
+    /// Build the request headers, failing on invalid names or values.
+    pub fn headers(&self) -> Result<Headers, String> {
+        let headers = Headers::new();
+        for (name, value) in &self.headers {
+            headers
+                .set(name, value)
+                .map_err(|e| format!("Invalid header `{name}`: {e}"))?;
+        }
+
+        Ok(headers)
+    }
+}
+
+/// Whether the value of the header `name` may hold credentials.
+pub fn is_sensitive(name: &str) -> bool {
+    let name = name.to_ascii_lowercase();
+    name == "cookie"
+        || ["auth", "token", "secret", "key", "password", "session"]
+            .iter()
+            .any(|word| name.contains(word))
+}
+
+/// Whether sensitive headers may be sent to `url`: only over HTTPS, to the origin of the requested
+/// `target_url`.
+pub fn sends_credentials(url: &str, target_url: &Url) -> bool {
+    Url::parse(url).is_ok_and(|url| url.scheme() == "https" && url.origin() == target_url.origin())
+}
+
+/// Remove the sensitive headers from `headers`.
+pub fn strip_sensitive(headers: &Headers) {
+    let sensitive: Vec<String> = headers.keys().filter(|name| is_sensitive(name)).collect();
+    for name in sensitive {
+        headers.delete(&name).ok();
+    }
+}
+
+/// A short hash of `value`, so cache keys tell secrets apart without containing them.
+fn digest(value: &str) -> String {
+    let mut hasher = DefaultHasher::new();
+    value.hash(&mut hasher);
+    format!("{:016x}", hasher.finish())
+}
+
+fn redact_headers<S: Serializer>(
+    headers: &BTreeMap<String, String>,
+    serializer: S,
+) -> Result<S::Ok, S::Error> {
+    serializer.collect_map(headers.iter().map(|(name, value)| {
+        let value = if is_sensitive(name) { REDACTED } else { value };
+        (name, value)
+    }))
+}
+
+/// Serialize request headers for a cache key, hashing the values of sensitive ones.
+pub fn digest_headers<S: Serializer>(
+    headers: &Option<BTreeMap<String, String>>,
+    serializer: S,
+) -> Result<S::Ok, S::Error> {
+    serializer.collect_map(headers.iter().flatten().map(|(name, value)| {
+        let value = if is_sensitive(name) {
+            digest(value)
+        } else {
+            value.clone()
+        };
+        (name, value)
+    }))
+}
+
+/// Serialize a request body for a cache key as its hash.
+pub fn digest_body<S: Serializer>(body: &Option<String>, serializer: S) -> Result<S::Ok, S::Error> {
+    body.as_deref().map(digest).serialize(serializer)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn sends_credentials_over_https_to_the_requested_origin_only() {
+        let target_url = Url::parse("https://example.com/login").unwrap();
+
+        assert!(sends_credentials("https://example.com", &target_url));
+        assert!(sends_credentials(
+            "https://example.com/account#top",
+            &target_url
+        ));
+        assert!(!sends_credentials("https://www.example.com", &target_url));
+        assert!(!sends_credentials("https://example.com:8443", &target_url));
+        assert!(!sends_credentials("http://example.com", &target_url));
+
+        let target_url = Url::parse("http://example.com").unwrap();
+        assert!(!sends_credentials("http://example.com", &target_url));
+    }
+}
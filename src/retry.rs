@@ -0,0 +1,146 @@
+use std::time::Duration;
+
+use serde::{Deserialize, Serialize};
+
+use crate::failure::FailureReason;
+use crate::{InputUrl, ProbeResult, Status};
+
+/// Upper bound of the `retries` option, each retry costs one more subrequest.
+pub const MAX_RETRIES: u32 = 5;
+const DEFAULT_BACKOFF_MS: u64 = 500;
+const DEFAULT_RETRY_ON: [RetryClass; 3] = [
+    RetryClass::Timeout,
+    RetryClass::ServerError,
+    RetryClass::Connection,
+];
+
+/// A class of failures worth retrying.
+#[derive(Deserialize, Serialize, Clone, Copy, PartialEq)]
+#[serde(rename_all = "kebab-case")]
+pub enum RetryClass {
+    Timeout,
+    #[serde(rename = "5xx")]
+    ServerError,
+    Connection,
+    Dns,
+}
+
+impl RetryClass {
+    fn covers(self, reason: FailureReason) -> bool {
+        match self {
+            RetryClass::Timeout => reason == FailureReason::Timeout,
+            RetryClass::ServerError => matches!(
+                reason,
+                FailureReason::Http5xx | FailureReason::Cloudflare52x
+            ),
+            RetryClass::Connection => matches!(
+                reason,
+                FailureReason::ConnectionRefused
+                    | FailureReason::ConnectionReset
+                    | FailureReason::TlsError
+                    | FailureReason::FetchError
+            ),
+            RetryClass::Dns => matches!(
+                reason,
+                FailureReason::DnsNxdomain | FailureReason::DnsServfail
+            ),
+        }
+    }
+}
+
+/// How a failed probe is retried before it is reported.
+pub struct RetryPolicy {
+    pub retries: u32,
+    backoff_ms: u64,
+    /// Upper bound of the total time spent waiting between attempts.
+    max_backoff_ms: u64,
+    retry_on: Vec<RetryClass>,
+}
+
+impl RetryPolicy {
+    pub fn new(input: &InputUrl, max_backoff_ms: u64) -> Self {
+        Self {
+            retries: input.retries.unwrap_or(0).min(MAX_RETRIES),
+            backoff_ms: input.retry_backoff_ms.unwrap_or(DEFAULT_BACKOFF_MS),
+            max_backoff_ms,
+            retry_on: input
+                .retry_on
+                .clone()
+                .unwrap_or_else(|| DEFAULT_RETRY_ON.to_vec()),
+        }
+    }
+
+    /// Whether `result`, the outcome of the given attempt (starting at 1), should be retried.
+    pub fn should_retry(&self, attempt: u32, result: &ProbeResult) -> bool {
+        attempt <= self.retries
+            && result
+                .failure_reason
+                .is_some_and(|reason| self.retry_on.iter().any(|class| class.covers(reason)))
+    }
+
+    /// Exponential backoff to wait after the given failed attempt (starting at 1), shortened so
+    /// that all the waits together stay within `max_backoff_ms`.
+    pub fn backoff(&self, attempt: u32) -> Duration {
+        let delay = |attempt: u32| self.backoff_ms.saturating_mul(1 << (attempt - 1));
+        let waited = (1..attempt).fold(0, |waited: u64, attempt| {
+            waited.saturating_add(delay(attempt))
+        });
+
+        Duration::from_millis(delay(attempt).min(self.max_backoff_ms.saturating_sub(waited)))
+    }
+}
+
+/// A single attempt of a retried probe.
+#[derive(Serialize, Deserialize, Clone)]
+pub struct Attempt {
+    pub status: Status,
+    pub status_code: Option<u16>,
+    pub status_text: String,
+    pub failure_reason: Option<FailureReason>,
+    pub response_time_ms: u64,
+}
+
+impl From<&ProbeResult> for Attempt {
+    fn from(result: &ProbeResult) -> Self {
+        Self {
+            status: result.status,
+            status_code: result.status_code,
+            status_text: result.status_text.clone(),
+            failure_reason: result.failure_reason,
+            response_time_ms: result.response_time_ms,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn caps_the_total_backoff() {
+        let policy = RetryPolicy {
+            retries: MAX_RETRIES,
+            backoff_ms: 500,
+            max_backoff_ms: 2_000,
+            retry_on: Vec::new(),
+        };
+
+        let waits: Vec<u128> = (1..=MAX_RETRIES)
+            .map(|attempt| policy.backoff(attempt).as_millis())
+            .collect();
+        assert_eq!(waits, [500, 1_000, 500, 0, 0]);
+    }
+
+    #[test]
+    fn does_not_overflow() {
+        let policy = RetryPolicy {
+            retries: MAX_RETRIES,
+            backoff_ms: u64::MAX,
+            max_backoff_ms: 60_000,
+            retry_on: Vec::new(),
+        };
+
+        assert_eq!(policy.backoff(1), Duration::from_millis(60_000));
+        assert_eq!(policy.backoff(MAX_RETRIES), Duration::ZERO);
+    }
+}
@@ -0,0 +1,474 @@
+//! DNS wire format (RFC 1035) encoding and decoding, as exchanged with RFC 8484 DoH servers.
+
+use std::net::{Ipv4Addr, Ipv6Addr};
+
+use crate::dns::{self, Answer, DnsRecord, RecordType};
+
+const FLAG_RD: u16 = 0x0100;
+const FLAG_AD: u16 = 0x0020;
+const FLAG_CD: u16 = 0x0010;
+const CLASS_IN: u16 = 1;
+const TYPE_OPT: u16 = 41;
+const EDNS_PAYLOAD_SIZE: u16 = 4096;
+const EDNS_FLAG_DO: u32 = 0x8000;
+/// Longest name in wire format, labels with their length byte and the root label included.
+const MAX_NAME_LENGTH: usize = 255;
+
+/// DNSSEC flags of a query.
+#[derive(Clone, Copy, Default)]
+pub struct QueryFlags {
+    /// CD: ask the resolver not to validate the answer.
+    pub checking_disabled: bool,
+    /// DO: ask for the `RRSIG` records along with the answer, with an EDNS record (RFC 3225).
+    pub dnssec_ok: bool,
+}
+
+/// Encode a recursive query for the `record_type` records of `name`. The AD bit is set so the
+/// resolver reports whether it validated the answer (RFC 6840, section 5.7), and the ID is 0 so
+/// GET queries are cacheable (RFC 8484, section 4.1).
+pub fn encode_query(
+    name: &str,
+    record_type: RecordType,
+    flags: QueryFlags,
+) -> Result<Vec<u8>, String> {
+    let mut header_flags = FLAG_RD | FLAG_AD;
+    if flags.checking_disabled {
+        header_flags |= FLAG_CD;
+    }
+
+    let mut message = Vec::with_capacity(512);
+    message.extend_from_slice(&0u16.to_be_bytes());
+    message.extend_from_slice(&header_flags.to_be_bytes());
+    // QDCOUNT, ANCOUNT, NSCOUNT and ARCOUNT.
+    for count in [1, 0, 0, u16::from(flags.dnssec_ok)] {
+        message.extend_from_slice(&count.to_be_bytes());
+    }
+
+    let name = name.trim_end_matches('.');
+    if name.len() > 253 {
+        return Err(format!("Name `{name}` is too long."));
+    }
+    for label in name.split('.').filter(|label| !label.is_empty()) {
+        if label.len() > 63 {
+            return Err(format!("Label `{label}` of `{name}` is too long."));
+        }
+        message.push(label.len() as u8);
+        message.extend_from_slice(label.as_bytes());
+    }
+    message.push(0);
+
+    message.extend_from_slice(&record_type.code().to_be_bytes());
+    message.extend_from_slice(&CLASS_IN.to_be_bytes());
+
+    if flags.dnssec_ok {
+        // OPT pseudo-record: root name, payload size as class, DO flag in the TTL, no options.
+        message.push(0);
+        message.extend_from_slice(&TYPE_OPT.to_be_bytes());
+        message.extend_from_slice(&EDNS_PAYLOAD_SIZE.to_be_bytes());
+        message.extend_from_slice(&EDNS_FLAG_DO.to_be_bytes());
+        message.extend_from_slice(&0u16.to_be_bytes());
+    }
+
+    Ok(message)
+}
+
+/// Decode the response code, AD flag and answer records of a response.
+pub fn decode_response(message: &[u8]) -> Result<Answer, String> {
+    let mut reader = Reader { message, offset: 0 };
+
+    let _id = reader.u16()?;
+    let flags = reader.u16()?;
+    let questions = reader.u16()?;
+    let answers = reader.u16()?;
+    // The authority and additional sections are not needed.
+    reader.skip(4)?;
+
+    for _ in 0..questions {
+        reader.name()?;
+        reader.skip(4)?;
+    }
+
+    let mut records = Vec::with_capacity(answers.into());
+    for _ in 0..answers {
+        let name = reader.name()?;
+        let record_type = reader.u16()?;
+        let _class = reader.u16()?;
+        let ttl = reader.u32()?;
+        let length = usize::from(reader.u16()?);
+        let end = reader.offset + length;
+        if end > message.len() {
+            return Err("Truncated record data.".to_string());
+        }
+
+        let data = reader.rdata(record_type, end)?;
+        reader.offset = end;
+
+        records.push(DnsRecord {
+            name,
+            record_type: dns::type_name(record_type),
+            ttl,
+            data,
+        });
+    }
+
+    Ok(Answer {
+        rcode: u64::from(flags & 0x000f),
+        authenticated: flags & FLAG_AD != 0,
+        records,
+    })
+}
+
+/// Encode `bytes` as unpadded base64url, as required by the `dns` parameter of GET queries.
+pub fn base64url(bytes: &[u8]) -> String {
+    const ALPHABET: &[u8; 64] = b"ABCDEFGHIJKLMNOPQRSTUVWXYZabcdefghijklmnopqrstuvwxyz0123456789-_";
+
+    let mut encoded = String::with_capacity(bytes.len().div_ceil(3) * 4);
+    for chunk in bytes.chunks(3) {
+        let triple = chunk.iter().enumerate().fold(0u32, |triple, (i, &byte)| {
+            triple | (u32::from(byte) << (16 - 8 * i))
+        });
+
+        for i in 0..=chunk.len() {
+            encoded.push(ALPHABET[((triple >> (18 - 6 * i)) & 0x3f) as usize] as char);
+        }
+    }
+
+    encoded
+}
+
+struct Reader<'a> {
+    message: &'a [u8],
+    offset: usize,
+}
+
+impl Reader<'_> {
+    fn bytes(&mut self, length: usize) -> Result<&[u8], String> {
+        let bytes = self
+            .message
+            .get(self.offset..self.offset + length)
+            .ok_or("Truncated DNS message.")?;
+        self.offset += length;
+        Ok(bytes)
+    }
+
+    fn skip(&mut self, length: usize) -> Result<(), String> {
+        self.bytes(length).map(|_| ())
+    }
+
+    fn u8(&mut self) -> Result<u8, String> {
+        Ok(self.bytes(1)?[0])
+    }
+
+    fn u16(&mut self) -> Result<u16, String> {
+        let bytes = self.bytes(2)?;
+        Ok(u16::from_be_bytes([bytes[0], bytes[1]]))
+    }
+
+    fn u32(&mut self) -> Result<u32, String> {
+        let bytes = self.bytes(4)?;
+        Ok(u32::from_be_bytes([bytes[0], bytes[1], bytes[2], bytes[3]]))
+    }
+
+    /// Read a possibly compressed name, in presentation format with its trailing dot.
+    ///
+    /// Compression pointers must point backwards, and the name must fit in `MAX_NAME_LENGTH`, so a
+    /// pointer loop always ends with an error.
+    fn name(&mut self) -> Result<String, String> {
+        let mut name = String::new();
+        let mut name_length = 1;
+        let mut offset = self.offset;
+        let mut resume = None;
+
+        loop {
+            let length = *self.message.get(offset).ok_or("Truncated name.")?;
+            match length {
+                0 => {
+                    self.offset = resume.unwrap_or(offset + 1);
+                    if name.is_empty() {
+                        name.push('.');
+                    }
+                    return Ok(name);
+                }
+                0xc0.. => {
+                    let low = *self.message.get(offset + 1).ok_or("Truncated name.")?;
+                    let target = usize::from(u16::from_be_bytes([length & 0x3f, low]));
+                    if target >= offset {
+                        return Err("Forward compression pointer in name.".to_string());
+                    }
+                    resume.get_or_insert(offset + 2);
+                    offset = target;
+                }
+                1..=63 => {
+                    name_length += 1 + usize::from(length);
+                    if name_length > MAX_NAME_LENGTH {
+                        return Err("Name is too long.".to_string());
+                    }
+
+                    let label = self
+                        .message
+                        .get(offset + 1..offset + 1 + usize::from(length))
+                        .ok_or("Truncated name.")?;
+                    name.push_str(&String::from_utf8_lossy(label));
+                    name.push('.');
+                    offset += 1 + usize::from(length);
+                }
+                _ => return Err(format!("Invalid label length {length}.")),
+            }
+        }
+    }
+
+    /// Read the data of a record ending at `end`, in presentation format. Unknown types are
+    /// returned in the generic `\# length hex` format (RFC 3597).
+    fn rdata(&mut self, record_type: u16, end: usize) -> Result<String, String> {
+        let data = match record_type {
+            1 => {
+                let bytes = self.bytes(4)?;
+                Ipv4Addr::new(bytes[0], bytes[1], bytes[2], bytes[3]).to_string()
+            }
+            28 => {
+                let bytes: [u8; 16] = self.bytes(16)?.try_into().map_err(|_| "Invalid AAAA.")?;
+                Ipv6Addr::from(bytes).to_string()
+            }
+            // CNAME, NS and PTR.
+            2 | 5 | 12 => self.name()?,
+            15 => format!("{} {}", self.u16()?, self.name()?),
+            16 => {
+                let mut strings = Vec::new();
+                while self.offset < end {
+                    let length = usize::from(self.u8()?);
+                    strings.push(quote(self.bytes(length)?));
+                }
+                strings.join(" ")
+            }
+            33 => format!(
+                "{} {} {} {}",
+                self.u16()?,
+                self.u16()?,
+                self.u16()?,
+                self.name()?
+            ),
+            257 => {
+                let flags = self.u8()?;
+                let tag_length = usize::from(self.u8()?);
+                let tag = String::from_utf8_lossy(self.bytes(tag_length)?).into_owned();
+                let value = self.bytes(end.saturating_sub(self.offset))?;
+                format!("{flags} {tag} {}", quote(value))
+            }
+            _ => {
+                let bytes = self.bytes(end.saturating_sub(self.offset))?;
+                let hex = bytes.iter().map(|b| format!("{b:02x}")).collect::<String>();
+                format!("\\# {} {hex}", bytes.len())
+            }
+        };
+
+        Ok(data)
+    }
+}
+
+/// Quote a character string, escaping quotes and backslashes.
+fn quote(bytes: &[u8]) -> String {
+    let text = String::from_utf8_lossy(bytes);
+    format!("\"{}\"", text.replace('\\', "\\\\").replace('"', "\\\""))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Header of a response with the given flags, no question and `answers` answer records.
+    fn header(flags: u16, answers: u16) -> Vec<u8> {
+        [0, flags, 0, answers, 0, 0]
+            .iter()
+            .flat_map(|field: &u16| field.to_be_bytes())
+            .collect()
+    }
+
+    /// A record of `name` (already encoded) with the given type and data.
+    fn record(name: &[u8], record_type: u16, data: &[u8]) -> Vec<u8> {
+        let mut record = name.to_vec();
+        record.extend_from_slice(&record_type.to_be_bytes());
+        record.extend_from_slice(&CLASS_IN.to_be_bytes());
+        record.extend_from_slice(&300u32.to_be_bytes());
+        record.extend_from_slice(&(data.len() as u16).to_be_bytes());
+        record.extend_from_slice(data);
+        record
+    }
+
+    fn error(message: &[u8]) -> String {
+        decode_response(message).err().unwrap()
+    }
+
+    /// The encoded query for `name`, turned into a response with the given answer records.
+    fn response(name: &str, record_type: RecordType, records: &[Vec<u8>]) -> Vec<u8> {
+        let mut message = encode_query(name, record_type, QueryFlags::default()).unwrap();
+        // QR, RD, RA and AD.
+        message[2..4].copy_from_slice(&0x81a0u16.to_be_bytes());
+        message[6..8].copy_from_slice(&(records.len() as u16).to_be_bytes());
+        for record in records {
+            message.extend_from_slice(record);
+        }
+        message
+    }
+
+    #[test]
+    fn encodes_queries() {
+        let query = encode_query("www.example.com.", RecordType::A, QueryFlags::default()).unwrap();
+        assert_eq!(
+            base64url(&query),
+            "AAABIAABAAAAAAAAA3d3dwdleGFtcGxlA2NvbQAAAQAB"
+        );
+
+        let flags = QueryFlags {
+            checking_disabled: true,
+            dnssec_ok: true,
+        };
+        let query = encode_query("example.com", RecordType::Txt, flags).unwrap();
+        assert_eq!(&query[2..4], &0x0130u16.to_be_bytes());
+        assert_eq!(&query[10..12], &1u16.to_be_bytes());
+        assert_eq!(
+            &query[query.len() - 11..],
+            &[0, 0, 41, 16, 0, 0, 0, 0x80, 0, 0, 0]
+        );
+    }
+
+    #[test]
+    fn rejects_long_names() {
+        let label = "a".repeat(64);
+        assert!(encode_query(&label, RecordType::A, QueryFlags::default()).is_err());
+
+        let name = ["a"; 128].join(".");
+        assert!(encode_query(&name, RecordType::A, QueryFlags::default()).is_err());
+    }
+
+    #[test]
+    fn encodes_base64url_without_padding() {
+        assert_eq!(base64url(b""), "");
+        assert_eq!(base64url(b"f"), "Zg");
+        assert_eq!(base64url(b"fo"), "Zm8");
+        assert_eq!(base64url(b"foo"), "Zm9v");
+        assert_eq!(base64url(&[0xfb, 0xff, 0xbf]), "-_-_");
+    }
+
+    #[test]
+    fn decodes_its_own_queries_answered() {
+        // Answers point to the question name at offset 12.
+        let pointer = [0xc0, 12];
+        let message = response(
+            "www.example.com",
+            RecordType::A,
+            &[
+                record(&pointer, 5, b"\x03cdn\xc0\x10"),
+                record(b"\x03cdn\x07example\x03com\x00", 1, &[93, 184, 215, 14]),
+                record(
+                    &pointer,
+                    28,
+                    &[0x20, 0x01, 0x0d, 0xb8, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 1],
+                ),
+                record(&pointer, 15, b"\x00\x0a\x02mx\xc0\x10"),
+                record(&pointer, 16, b"\x05v=spf\x09 say \"hi\""),
+                record(&pointer, 257, b"\x00\x05issueletsencrypt.org"),
+                record(&pointer, 99, &[0xde, 0xad]),
+            ],
+        );
+
+        let answer = decode_response(&message).unwrap();
+        assert_eq!(answer.rcode, 0);
+        assert!(answer.authenticated);
+
+        let records: Vec<(&str, &str, &str)> = answer
+            .records
+            .iter()
+            .map(|r| (r.name.as_str(), r.record_type.as_str(), r.data.as_str()))
+            .collect();
+        assert_eq!(
+            records,
+            [
+                ("www.example.com.", "CNAME", "cdn.example.com."),
+                ("cdn.example.com.", "A", "93.184.215.14"),
+                ("www.example.com.", "AAAA", "2001:db8::1"),
+                ("www.example.com.", "MX", "10 mx.example.com."),
+                ("www.example.com.", "TXT", r#""v=spf" " say \"hi\"""#),
+                ("www.example.com.", "CAA", r#"0 issue "letsencrypt.org""#),
+                ("www.example.com.", "TYPE99", r"\# 2 dead"),
+            ]
+        );
+        assert_eq!(answer.records[0].ttl, 300);
+    }
+
+    #[test]
+    fn decodes_response_codes() {
+        let mut message = response("example.com", RecordType::A, &[]);
+        message[3] = 0x83;
+
+        let answer = decode_response(&message).unwrap();
+        assert_eq!(answer.rcode, 3);
+        assert!(answer.records.is_empty());
+    }
+
+    #[test]
+    fn rejects_truncated_messages() {
+        let message = response(
+            "example.com",
+            RecordType::Mx,
+            &[record(&[0xc0, 12], 15, b"\x00\x0a\x02mx\xc0\x0c")],
+        );
+        assert!(decode_response(&message).is_ok());
+
+        for length in 0..message.len() {
+            assert!(decode_response(&message[..length]).is_err(), "{length}");
+        }
+    }
+
+    #[test]
+    fn rejects_record_data_past_the_message() {
+        let mut message = response("example.com", RecordType::A, &[]);
+        message[7] = 1;
+        message.extend_from_slice(&record(&[0xc0, 12], 1, &[127, 0, 0, 1]));
+        let length = message.len();
+        message[length - 5] = 5;
+
+        assert_eq!(error(&message), "Truncated record data.");
+    }
+
+    #[test]
+    fn rejects_pointer_loops() {
+        // A name pointing to itself.
+        let mut message = header(0x8180, 1);
+        message.extend_from_slice(&record(&[0xc0, 12], 1, &[127, 0, 0, 1]));
+        assert_eq!(error(&message), "Forward compression pointer in name.");
+
+        // A label followed by a pointer back to it.
+        let mut message = header(0x8180, 1);
+        message.extend_from_slice(&record(b"\x01a\xc0\x0c", 1, &[127, 0, 0, 1]));
+        assert_eq!(error(&message), "Name is too long.");
+    }
+
+    #[test]
+    fn rejects_forward_pointers() {
+        let mut message = header(0x8180, 1);
+        message.extend_from_slice(&record(&[0xc0, 14], 1, &[127, 0, 0, 1]));
+        message.extend_from_slice(b"\x07example\x00");
+        assert_eq!(error(&message), "Forward compression pointer in name.");
+    }
+
+    #[test]
+    fn decodes_names_with_many_labels() {
+        let name = ["a"; 120].join(".");
+        let message = response(
+            &name,
+            RecordType::A,
+            &[record(&[0xc0, 12], 1, &[127, 0, 0, 1])],
+        );
+
+        let answer = decode_response(&message).unwrap();
+        assert_eq!(answer.records[0].name, format!("{name}."));
+    }
+
+    #[test]
+    fn rejects_names_too_long() {
+        let labels = [b"\x01a".as_slice(); 128].concat();
+        let mut message = header(0x8180, 1);
+        message.extend_from_slice(&record(&[labels, vec![0]].concat(), 1, &[127, 0, 0, 1]));
+        assert_eq!(error(&message), "Name is too long.");
+    }
+}